@@ -5,6 +5,7 @@ pub(crate) mod parse {
     use std::fmt::Debug;
     use std::fs::File;
     use std::fs;
+    use ndarray::Array2;
 
     /** @todo convert return type to Result<impl Iterator<Item=String>, {Error}> on first error */
     pub fn read_lines(path: impl AsRef<Path>) -> impl Iterator<Item=String> {
@@ -17,6 +18,58 @@ pub(crate) mod parse {
         let content = fs::read_to_string(path.as_ref()).unwrap();
         content.split(',').map(|s| s.parse::<T>().expect("Unable to parse T")).collect()
     }
+
+    /// Rows and columns are 1-based, and refer to the lines of the parsed string, blank ones included
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum DigitGridParseError {
+        /// Neither a digit nor a trailing whitespace
+        InvalidChar { row: usize, column: usize, c: char },
+        /// Row whose number of columns differs from the first row's
+        RaggedRow { row: usize, nb_columns: usize, expected: usize },
+        /// No digit at all
+        Empty,
+        /// Unable to read the file
+        Io(io::ErrorKind),
+    }
+
+    impl From<DigitGridParseError> for io::Error {
+        fn from(e: DigitGridParseError) -> Self {
+            match e {
+                DigitGridParseError::Io(kind) => io::Error::from(kind),
+                e => io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)),
+            }
+        }
+    }
+
+    /// One row of digits per line. Trailing whitespaces and blank lines are ignored
+    pub fn digit_grid(s: &str) -> Result<Array2<u8>, DigitGridParseError> {
+        let mut digits: Vec<u8> = Vec::new();
+        let mut nb_rows: usize = 0;
+        let mut nb_columns: Option<usize> = None;
+
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let row = line.trim_end().chars().enumerate().map(|(j, c)| match c.to_digit(10) {
+                Some(d) => Ok(d as u8),
+                None => Err(DigitGridParseError::InvalidChar { row: i + 1, column: j + 1, c }),
+            }).collect::<Result<Vec<u8>, _>>()?;
+
+            let expected = *nb_columns.get_or_insert(row.len());
+            if row.len() != expected {
+                return Err(DigitGridParseError::RaggedRow { row: i + 1, nb_columns: row.len(), expected });
+            }
+
+            digits.extend(row);
+            nb_rows += 1;
+        }
+
+        let nb_columns = nb_columns.ok_or(DigitGridParseError::Empty)?;
+        Ok( Array2::from_shape_vec((nb_rows, nb_columns), digits).expect("rows all have nb_columns digits") )
+    }
+
+    pub fn read_digit_grid(path: impl AsRef<Path>) -> Result<Array2<u8>, DigitGridParseError> {
+        let content = fs::read_to_string(path).map_err(|e| DigitGridParseError::Io(e.kind()))?;
+        digit_grid(&content)
+    }
 }
 
 pub(crate) mod collections {
@@ -29,6 +82,32 @@ pub(crate) mod collections {
         for k in ite { *freqs.entry(k).or_default() += 1; }
         freqs
     }
+
+    /// Union-find over the elements 0..n, w/ path halving and union by size
+    pub struct DisjointSets { parents: Vec<usize>, sizes: Vec<usize> }
+
+    impl DisjointSets {
+        pub fn new(n: usize) -> Self { DisjointSets { parents: (0..n).collect(), sizes: vec![1; n] } }
+
+        /// Representative of the set containing `i`
+        pub fn find(&mut self, mut i: usize) -> usize {
+            while self.parents[i] != i {
+                self.parents[i] = self.parents[self.parents[i]];
+                i = self.parents[i];
+            }
+            i
+        }
+
+        /// **returns**: the representative of the merged set
+        pub fn union(&mut self, a: usize, b: usize) -> usize {
+            let (mut a, mut b) = (self.find(a), self.find(b));
+            if a == b { return a; }
+            if self.sizes[a] < self.sizes[b] { std::mem::swap(&mut a, &mut b); }
+            self.parents[b] = a;
+            self.sizes[a] += self.sizes[b];
+            a
+        }
+    }
 }
 
 
@@ -47,6 +126,288 @@ pub(crate) mod math {
     }
 }
 
+pub(crate) mod render {
+    use ndarray::Array2;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+    pub struct Rgb(pub u8, pub u8, pub u8);
+
+    pub const ANSI_RESET: &str = "\x1b[0m";
+    pub const ANSI_CLEAR: &str = "\x1b[2J\x1b[H";
+    /// Moves the cursor back to the top left corner, to redraw in place
+    pub const ANSI_HOME: &str = "\x1b[H";
+
+    /// 24-bit background colour escape code
+    pub fn ansi_bg(Rgb(r, g, b): Rgb) -> String { format!("\x1b[48;2;{};{};{}m", r, g, b) }
+
+    /// 24-bit foreground colour escape code
+    pub fn ansi_fg(Rgb(r, g, b): Rgb) -> String { format!("\x1b[38;2;{};{};{}m", r, g, b) }
+
+    /// Distinct colours for consecutive indexes: hues are spread by the golden angle
+    pub fn palette(i: usize) -> Rgb {
+        let hue = (i as f64 * 137.507_764) % 360.0;
+        hsv_to_rgb(hue, 0.55, 0.95)
+    }
+
+    fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> Rgb {
+        let c = value * saturation;
+        let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+        let m = value - c;
+        let (r, g, b) = match hue as u32 / 60 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let to_u8 = |v: f64| ((v + m) * 255.0).round() as u8;
+        Rgb(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Binary PPM (P6) image, each pixel drawn as a `scale` x `scale` square
+    pub fn ppm(pixels: &Array2<Rgb>, scale: usize) -> Vec<u8> {
+        let (nb_rows, nb_columns) = pixels.dim();
+        let mut image = format!("P6\n{} {}\n255\n", nb_columns * scale, nb_rows * scale).into_bytes();
+        for row in pixels.rows() {
+            for _ in 0..scale {
+                for &Rgb(r, g, b) in row { for _ in 0..scale { image.extend([r, g, b]); } }
+            }
+        }
+        image
+    }
+}
+
+/// Cellular automata on a 2D grid. A step is a synchronous update of every cell from the previous generation,
+/// followed by a cascade: firing cells propagate to their neighbours, which may fire in turn.
+pub(crate) mod automaton {
+    use std::collections::VecDeque;
+    use ndarray::Array2;
+
+    pub type Index = (usize, usize);
+
+    pub const VON_NEUMANN: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+    pub const MOORE: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+    /// What lies beyond the edges of the grid
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum Boundary<C> {
+        /// Nothing: edge cells have fewer neighbours
+        Bounded,
+        /// Edges wrap around. On grids smaller than the neighbourhood, a cell may be met several times or be its own
+        /// neighbour.
+        Torus,
+        /// Cells of a fixed value, seen by the synchronous update only: they never fire nor receive
+        Padded(C),
+    }
+
+    impl<C> Boundary<C> {
+        /// Cell of the grid at `offset` from `ij`, None if it's beyond the edges
+        pub fn neighbour(&self, (nb_rows, nb_cols): Index, (i, j): Index, (di, dj): (isize, isize)) -> Option<Index> {
+            match self {
+                Boundary::Torus => Some((
+                    (i as isize + di).rem_euclid(nb_rows as isize) as usize,
+                    (j as isize + dj).rem_euclid(nb_cols as isize) as usize,
+                )),
+                _ => {
+                    let (ni, nj) = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+                    if ni < nb_rows && nj < nb_cols { Some((ni, nj)) } else { None }
+                }
+            }
+        }
+
+        /// Cells of the grid around `ij` at the given offsets, `ij` itself excluded unless an offset is (0, 0)
+        pub fn neighbours<'a>(&'a self, dim: Index, ij: Index, offsets: &'a [(isize, isize)]) -> impl Iterator<Item=Index> + 'a {
+            offsets.iter().filter_map(move |&offset| self.neighbour(dim, ij, offset))
+        }
+    }
+
+    /// Every method has a no-op default, so that a rule only defines its synchronous part or its cascade
+    pub trait Rule {
+        type Cell: Clone;
+
+        /// Offsets of the neighbours, Moore by default
+        fn neighbourhood(&self) -> &[(isize, isize)] { &MOORE }
+        /// Next generation of a cell, from its neighbours in the previous one
+        fn update(&self, cell: &Self::Cell, _neighbours: &[&Self::Cell]) -> Self::Cell { cell.clone() }
+        /// Whether the cell fires, i.e. propagates to its neighbours
+        fn fires(&self, _cell: &Self::Cell) -> bool { false }
+        /// Applied to a cell when it fires
+        fn fire(&self, _cell: &mut Self::Cell) {}
+        /// Applied to each neighbour of a firing cell
+        fn receive(&self, _cell: &mut Self::Cell) {}
+        /// When true, a cell fires at most once per step, and receives nothing once fired.
+        /// Otherwise it keeps firing as long as `fires` holds.
+        fn fires_once(&self) -> bool { true }
+    }
+
+    /// Step w/ a bounded grid
+    /// **returns**: number of firings during the step
+    pub fn step<R: Rule>(rule: &R, grid: &mut Array2<R::Cell>) -> usize { step_with(rule, grid, &Boundary::Bounded) }
+
+    /// **returns**: number of firings during the step
+    pub fn step_with<R: Rule>(rule: &R, grid: &mut Array2<R::Cell>, boundary: &Boundary<R::Cell>) -> usize {
+        let dim = grid.dim();
+        let offsets = rule.neighbourhood();
+        let neighbours = |ij: Index| boundary.neighbours(dim, ij, offsets);
+
+        // 1. synchronous update
+        let previous = grid.clone();
+        for (ij, cell) in grid.indexed_iter_mut() {
+            let neighbours: Vec<&R::Cell> = offsets.iter().filter_map(|&offset| {
+                match (boundary.neighbour(dim, ij, offset), boundary) {
+                    (Some(n), _) => Some(&previous[n]),
+                    (None, Boundary::Padded(padding)) => Some(padding),
+                    (None, _) => None,
+                }
+            }).collect();
+            *cell = rule.update(&previous[ij], &neighbours);
+        }
+
+        // 2. cascade
+        let mut fired: Array2<bool> = Array2::from_elem(dim, false);
+        let mut to_visit: VecDeque<Index> = grid.indexed_iter().filter(|(_, c)| rule.fires(c)).map(|(ij, _)| ij).collect();
+        let mut nb_fired: usize = 0;
+        while let Some(ij) = to_visit.pop_front() {
+            if rule.fires_once() && fired[ij] || !rule.fires(&grid[ij]) { continue; }
+            fired[ij] = true;
+            nb_fired += 1;
+            rule.fire(&mut grid[ij]);
+            for n in neighbours(ij) {
+                if rule.fires_once() && fired[n] { continue; }
+                rule.receive(&mut grid[n]);
+                if rule.fires(&grid[n]) { to_visit.push_back(n); }
+            }
+            if !rule.fires_once() && rule.fires(&grid[ij]) { to_visit.push_back(ij); }
+        }
+        nb_fired
+    }
+
+    /// Conway's Game of Life
+    pub struct Life;
+
+    impl Rule for Life {
+        type Cell = bool;
+
+        fn update(&self, &alive: &bool, neighbours: &[&bool]) -> bool {
+            let nb_alive = neighbours.iter().filter(|&&&n| n).count();
+            nb_alive == 3 || alive && nb_alive == 2
+        }
+    }
+
+    /// Abelian sandpile: a cell holding 4 grains or more topples one grain to each of its 4 neighbours, until the
+    /// grid is stable. Grains falling off the grid are lost.
+    pub struct Sandpile;
+
+    impl Rule for Sandpile {
+        type Cell = u32;
+
+        fn neighbourhood(&self) -> &[(isize, isize)] { &VON_NEUMANN }
+        fn fires(&self, &grains: &u32) -> bool { grains >= 4 }
+        fn fire(&self, grains: &mut u32) { *grains -= 4; }
+        fn receive(&self, grains: &mut u32) { *grains += 1; }
+        fn fires_once(&self) -> bool { false }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use ndarray::array;
+        use super::*;
+
+        #[test]
+        fn test_life_blinker() {
+            let vertical = array![
+                [false, false, false, false, false],
+                [false, false, true,  false, false],
+                [false, false, true,  false, false],
+                [false, false, true,  false, false],
+                [false, false, false, false, false],
+            ];
+            let mut grid = vertical.clone();
+            step(&Life, &mut grid);
+            assert_eq!(grid, vertical.t());
+            step(&Life, &mut grid);
+            assert_eq!(grid, vertical);
+        }
+
+        #[test]
+        fn test_life_boundaries() {
+            // A glider crosses the torus back to its start in 4 steps per cell
+            let mut glider: Array2<bool> = Array2::from_elem((5, 5), false);
+            for ij in [(0, 1), (1, 2), (2, 0), (2, 1), (2, 2)] { glider[ij] = true; }
+            let mut grid = glider.clone();
+            for _ in 0..4 { step_with(&Life, &mut grid, &Boundary::Torus); }
+            assert_ne!(grid, glider);
+            for _ in 4..20 { step_with(&Life, &mut grid, &Boundary::Torus); }
+            assert_eq!(grid, glider);
+
+            // Bounded, it ends up as a block in the corner
+            let mut grid = glider.clone();
+            for _ in 0..20 { step(&Life, &mut grid); }
+            assert_eq!(grid.iter().filter(|&&alive| alive).count(), 4);
+
+            // Living padding: edge cells have 3 living neighbours, corners 5
+            let mut grid: Array2<bool> = Array2::from_elem((3, 3), false);
+            step_with(&Life, &mut grid, &Boundary::Padded(true));
+            assert_eq!(grid, array![[false, true, false], [true, false, true], [false, true, false]]);
+        }
+
+        #[test]
+        fn test_sandpile() {
+            let mut grid: Array2<u32> = Array2::zeros((5, 5));
+            grid[(2, 2)] = 16;
+            let nb_topples = step(&Sandpile, &mut grid);
+            assert_eq!(grid, array![
+                [0, 0, 1, 0, 0],
+                [0, 2, 1, 2, 0],
+                [1, 1, 0, 1, 1],
+                [0, 2, 1, 2, 0],
+                [0, 0, 1, 0, 0],
+            ]);
+            assert_eq!(nb_topples, 9);
+
+            // Stable piles don't change
+            assert_eq!(step(&Sandpile, &mut grid), 0);
+        }
+
+        #[test]
+        fn test_neighbours() {
+            let bounded: Boundary<u8> = Boundary::Bounded;
+            assert_eq!(bounded.neighbours((3, 3), (0, 0), &MOORE).collect::<Vec<_>>(), vec![(0, 1), (1, 0), (1, 1)]);
+            assert_eq!(bounded.neighbours((3, 3), (1, 1), &VON_NEUMANN).count(), 4);
+            assert_eq!(bounded.neighbours((0, 0), (0, 0), &MOORE).count(), 0);
+            assert_eq!(Boundary::Padded(0).neighbours((3, 3), (2, 2), &MOORE).count(), 3);
+
+            let torus: Boundary<u8> = Boundary::Torus;
+            assert_eq!(
+                torus.neighbours((3, 4), (0, 0), &VON_NEUMANN).collect::<Vec<_>>(),
+                vec![(2, 0), (0, 3), (0, 1), (1, 0)]
+            );
+            assert!(!torus.neighbours((3, 4), (1, 1), &MOORE).any(|n| n == (1, 1)));
+
+            // Empty grids have no cell to step
+            let mut empty: Array2<u32> = Array2::zeros((0, 0));
+            assert_eq!(step_with(&Sandpile, &mut empty, &Boundary::Torus), 0);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod random {
+    /// Small linear congruential generator, to keep tests deterministic w/o pulling a rand dependency
+    pub struct Lcg { seed: u64 }
+
+    impl Lcg {
+        pub fn new(seed: u64) -> Self { Lcg { seed } }
+
+        /// **returns**: a number in 0..bound
+        pub fn next(&mut self, bound: u64) -> u64 {
+            self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.seed >> 33) % bound
+        }
+    }
+}
+
 pub(crate) mod macros {
     macro_rules! when { ($e:expr, $s:expr) => { 
         if ($e) { Some($s) } else { None } 
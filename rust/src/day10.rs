@@ -1,66 +1,424 @@
 use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufReader, Read};
 
 use itertools::Itertools;
-use rdxsort::RdxSort;
 
+/// Columns are 1-based character positions in the line
 #[derive(PartialEq, Eq, Clone, Debug)]
 enum ParseResult {
   Valid,
-  Incomplete(String), // Missing delimiters
-  Corrupted(char),
+  /// completion: missing delimiters, innermost first
+  /// openers: column of the opener each missing delimiter closes
+  Incomplete { completion: String, openers: Vec<usize> },
+  /// expected: closer of the innermost opened delimiter, if any
+  /// opener: column of that delimiter
+  Corrupted { found: char, column: usize, expected: Option<char>, opener: Option<usize> },
 }
 
-fn match_closing_delimiter(open: char) -> Option<char> {
-  match open {
-    '(' => Some(')'),
-    '[' => Some(']'),
-    '{' => Some('}'),
-    '<' => Some('>'),
-    _ => None
+impl ParseResult {
+  fn corrupting_char(&self) -> Option<char> {
+    match self { ParseResult::Corrupted { found, .. } => Some(*found), _ => None }
+  }
+
+  fn completion(&self) -> Option<&str> {
+    match self { ParseResult::Incomplete { completion, .. } => Some(completion), _ => None }
+  }
+
+  fn diagnose<'a>(&'a self, line: &'a str) -> Diagnostic<'a> { Diagnostic { line, result: self } }
+}
+
+/// Caret-style diagnostic: the line, then `^` under the faulty column and `-` under the related openers
+/// ```text
+/// {([(<{}[<>[]}>{[]{[(<()>
+///        -    ^ expected ']' to close '[' at column 8, found '}' at column 13
+/// ```
+struct Diagnostic<'a> { line: &'a str, result: &'a ParseResult }
+
+impl Display for Diagnostic<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let char_at = |column: usize| self.line.chars().nth(column - 1).unwrap_or(' ');
+    let (markers, message): (Vec<(usize, char)>, String) = match self.result {
+      ParseResult::Valid => (vec![], "valid".to_string()),
+      ParseResult::Incomplete { completion, openers } => (
+        openers.iter().map(|&o| (o, '-')).chain([(self.line.chars().count() + 1, '^')]).collect(),
+        format!("incomplete, missing \"{}\"", completion),
+      ),
+      ParseResult::Corrupted { found, column, expected: Some(expected), opener: Some(opener) } => (
+        vec![(*opener, '-'), (*column, '^')],
+        format!("expected '{}' to close '{}' at column {}, found '{}' at column {}", expected, char_at(*opener), opener, found, column),
+      ),
+      ParseResult::Corrupted { found, column, .. } => (
+        vec![(*column, '^')],
+        format!("unexpected '{}' at column {}", found, column),
+      ),
+    };
+
+    writeln!(f, "{}", self.line)?;
+    let mut underline = vec![' '; markers.iter().map(|&(col, _)| col).max().unwrap_or(0)];
+    for (col, marker) in markers { underline[col - 1] = marker; }
+    if !underline.is_empty() { write!(f, "{} ", underline.into_iter().collect::<String>())?; }
+    write!(f, "{}", message)
+  }
+}
+
+/// Characters which are neither delimiters nor quotes
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Ignored {
+  /// Treated as unexpected closers, i.e. corrupt the line
+  None,
+  /// Only the listed characters are skipped
+  Chars(Vec<char>),
+  /// All skipped
+  All,
+}
+
+/// Grammar of the bracket checker.
+/// - pairs: nesting (open, close) delimiters
+/// - quotes: (open, close) delimiters suspending the nesting until closed, e.g. string literals
+/// - escape: character making the next one literal within quotes
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct DelimiterSet { pairs: Vec<(char, char)>, quotes: Vec<(char, char)>, escape: Option<char>, ignored: Ignored }
+
+impl DelimiterSet {
+  fn new(pairs: &[(char, char)]) -> Self {
+    DelimiterSet { pairs: pairs.to_vec(), quotes: Vec::new(), escape: None, ignored: Ignored::None }
+  }
+
+  /// `()[]{}<>`, any other character is corrupted
+  fn puzzle() -> Self { DelimiterSet::new(&[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')]) }
+
+  /// `()[]{}`, w/ `"` and `'` string literals, `\` escapes, and anything else ignored. `<>` are left out as they
+  /// are also comparison operators.
+  fn code() -> Self {
+    DelimiterSet::new(&[('(', ')'), ('[', ']'), ('{', '}')])
+      .with_quotes(&[('"', '"'), ('\'', '\'')])
+      .with_escape('\\')
+      .ignoring_others()
+  }
+
+  fn with_quotes(self, quotes: &[(char, char)]) -> Self { DelimiterSet { quotes: quotes.to_vec(), ..self } }
+  fn with_escape(self, escape: char) -> Self { DelimiterSet { escape: Some(escape), ..self } }
+  fn with_ignored(self, ignored: &[char]) -> Self { DelimiterSet { ignored: Ignored::Chars(ignored.to_vec()), ..self } }
+  fn ignoring_others(self) -> Self { DelimiterSet { ignored: Ignored::All, ..self } }
+
+  fn closing_delimiter(&self, open: char) -> Option<char> {
+    self.pairs.iter().find(|&&(o, _)| o == open).map(|&(_, c)| c)
+  }
+
+  fn opening_delimiter(&self, close: char) -> Option<char> {
+    self.pairs.iter().find(|&&(_, c)| c == close).map(|&(o, _)| o)
+  }
+
+  fn closing_quote(&self, open: char) -> Option<char> {
+    self.quotes.iter().find(|&&(o, _)| o == open).map(|&(_, c)| c)
+  }
+
+  fn is_closer(&self, c: char) -> bool { self.pairs.iter().any(|&(_, close)| close == c) }
+
+  fn is_ignored(&self, c: char) -> bool {
+    match &self.ignored {
+      Ignored::None => false,
+      Ignored::Chars(chars) => chars.contains(&c),
+      Ignored::All => !self.is_closer(c),
+    }
   }
 }
 
+/// Incremental parser state, fed one char or one chunk at a time.
+/// Columns keep counting across chunks, until `reset`
+struct BracketParser<'a> {
+  delimiters: &'a DelimiterSet,
+  // Expected closers, flagged when quoting, w/ the column of their opener
+  stack: Vec<(char, bool, usize)>,
+  escaped: bool,
+  column: usize,
+  corrupted: Option<ParseResult>,
+}
 
-fn parse_line<S: AsRef<str>>(line: S) -> ParseResult {
-  let mut stack: Vec<char> = Vec::new();
-  for c in line.as_ref().chars() {
-    if let Some(close) = match_closing_delimiter(c) { stack.push(close); }
-    else {
-      match stack.pop() {
-        None => return ParseResult::Corrupted(c),
-        Some(close_expected) => if c != close_expected { return ParseResult::Corrupted(c) }
+impl<'a> BracketParser<'a> {
+  fn new(delimiters: &'a DelimiterSet) -> Self {
+    BracketParser { delimiters, stack: Vec::new(), escaped: false, column: 0, corrupted: None }
+  }
+
+  fn depth(&self) -> usize { self.stack.len() }
+  fn expected_closer(&self) -> Option<char> { self.stack.last().map(|&(close, _, _)| close) }
+  fn is_corrupted(&self) -> bool { self.corrupted.is_some() }
+
+  /// **returns**: the `Corrupted` result, as soon as `c` corrupts the input. Once corrupted, further chars are skipped
+  fn push(&mut self, c: char) -> Option<ParseResult> {
+    if self.is_corrupted() { return None }
+    self.column += 1;
+    let column = self.column;
+    let delimiters = self.delimiters;
+    match self.stack.last() {
+      Some(_) if self.escaped => self.escaped = false,
+      Some(&(close, true, _)) if c == close => { self.stack.pop(); },
+      Some(&(_, true, _)) => self.escaped = delimiters.escape == Some(c),
+      _ => {
+        if let Some(close) = delimiters.closing_quote(c) { self.stack.push((close, true, column)); }
+        else if let Some(close) = delimiters.closing_delimiter(c) { self.stack.push((close, false, column)); }
+        else if !delimiters.is_ignored(c) {
+          let corrupted = match self.stack.pop() {
+            None => ParseResult::Corrupted { found: c, column, expected: None, opener: None },
+            Some((close_expected, _, _)) if c == close_expected => return None,
+            Some((close_expected, _, opener)) =>
+              ParseResult::Corrupted { found: c, column, expected: Some(close_expected), opener: Some(opener) },
+          };
+          self.corrupted = Some(corrupted.clone());
+          return Some(corrupted)
+        }
       }
     }
+    None
+  }
+
+  /// **returns**: the `Corrupted` result, if the chunk corrupts the input
+  fn feed(&mut self, chunk: &str) -> Option<ParseResult> { chunk.chars().find_map(|c| self.push(c)) }
+
+  /// Result of everything pushed so far
+  fn finish(&self) -> ParseResult {
+    if let Some(corrupted) = &self.corrupted { return corrupted.clone() }
+    if self.stack.is_empty() {
+      ParseResult::Valid
+    } else {
+      let (completion, openers) = self.stack.iter().rev().map(|&(close, _, opener)| (close, opener)).unzip();
+      ParseResult::Incomplete { completion, openers }
+    }
+  }
+
+  fn reset(&mut self) {
+    self.stack.clear();
+    self.escaped = false;
+    self.column = 0;
+    self.corrupted = None;
   }
+}
+
+fn parse_line<S: AsRef<str>>(line: S, delimiters: &DelimiterSet) -> ParseResult {
+  let mut parser = BracketParser::new(delimiters);
+  parser.feed(line.as_ref());
+  parser.finish()
+}
+
+/// Checks each line of a stream w/o buffering it. A corrupted line is reported as soon as the corruption is read,
+/// the rest of it is then skipped. `\r` are dropped
+fn parse_lines<'a, I>(chars: I, delimiters: &'a DelimiterSet) -> impl Iterator<Item=io::Result<ParseResult>> + 'a
+  where I: IntoIterator<Item=io::Result<char>>, I::IntoIter: 'a {
+  let mut chars = chars.into_iter();
+  let mut parser = BracketParser::new(delimiters);
+  std::iter::from_fn(move || {
+    loop {
+      match chars.next() {
+        // Last line w/o trailing newline, unless already reported as corrupted
+        None => {
+          let pending = parser.column > 0 && !parser.is_corrupted();
+          let result = parser.finish();
+          parser.reset();
+          return if pending { Some(Ok(result)) } else { None }
+        },
+        Some(Err(e)) => return Some(Err(e)),
+        Some(Ok('\r')) => continue,
+        Some(Ok('\n')) => {
+          let was_corrupted = parser.is_corrupted();
+          let result = parser.finish();
+          parser.reset();
+          if !was_corrupted { return Some(Ok(result)) }
+        },
+        Some(Ok(c)) => if let Some(corrupted) = parser.push(c) { return Some(Ok(corrupted)) },
+      }
+    }
+  })
+}
 
-  if stack.is_empty() { 
-    ParseResult::Valid 
-  } else {
-    ParseResult::Incomplete(stack.into_iter().rev().collect())
+/// UTF-8 chars of a reader, e.g. `io::stdin()`
+fn read_chars(reader: impl Read) -> impl Iterator<Item=io::Result<char>> {
+  let mut bytes = BufReader::new(reader).bytes();
+  std::iter::from_fn(move || {
+    let first = match bytes.next()? { Ok(b) => b, Err(e) => return Some(Err(e)) };
+    let mut buf = [first, 0, 0, 0];
+    let len = match first.leading_ones() { 0 => 1, n @ 2..=4 => n as usize, _ => 1 };
+    for b in buf.iter_mut().take(len).skip(1) {
+      match bytes.next() { Some(Ok(next)) => *b = next, Some(Err(e)) => return Some(Err(e)), None => break }
+    }
+    Some(std::str::from_utf8(&buf[..len])
+      .map(|s| s.chars().next().unwrap())
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+  })
+}
+
+/// Repaired line, w/ the number of edits (insertions, deletions or substitutions) it took
+#[derive(PartialEq, Eq, Debug)]
+struct Repair { line: String, edits: usize }
+
+/// Minimum edits making the line valid, i.e. an edit distance to the balanced lines. Ignored characters are kept as is,
+/// other characters are either delimiters to match or corrupted ones to remove.
+/// Unmatched openers are closed at the end of their enclosing block, unmatched closers are opened right before.
+/// Quoted spans are read as `BracketParser` does, and left untouched: an unterminated one is closed at the end of the
+/// line, before the missing closers, and w/o its dangling escape if any.
+/// Runs in O(n³) w/ n the number of delimiters
+fn repair_line<S: AsRef<str>>(line: S, delimiters: &DelimiterSet) -> Repair {
+  let chars = line.as_ref().chars().collect_vec();
+
+  // Positions of the delimiters to match, and closer of the quote left open if any
+  let mut tokens: Vec<usize> = Vec::new();
+  let mut quote: Option<char> = None;
+  let mut escaped = false;
+  for (pos, &c) in chars.iter().enumerate() {
+    match quote {
+      Some(_) if escaped => escaped = false,
+      Some(close) if c == close => quote = None,
+      Some(_) => escaped = delimiters.escape == Some(c),
+      None => {
+        if let Some(close) = delimiters.closing_quote(c) { quote = Some(close); }
+        else if delimiters.closing_delimiter(c).is_some() || !delimiters.is_ignored(c) { tokens.push(pos); }
+      }
+    }
+  }
+  let n = tokens.len();
+  let match_cost = |a: char, b: char| {
+    if delimiters.closing_delimiter(a) == Some(b) { 0 }
+    else if delimiters.closing_delimiter(a).is_some() || delimiters.is_closer(b) { 1 }
+    else { 2 }
+  };
+
+  // cost[i][j]: edits making tokens[i..j] balanced.
+  // pairing[i][j]: token matched w/ tokens[i], or None if it's left unmatched
+  let mut cost = vec![vec![0; n + 1]; n + 1];
+  let mut pairing = vec![vec![None; n + 1]; n + 1];
+  for len in 1..=n {
+    for i in 0..=n - len {
+      let j = i + len;
+      let mut best = (1 + cost[i + 1][j], None);
+      for k in i + 1..j {
+        let c = match_cost(chars[tokens[i]], chars[tokens[k]]) + cost[i + 1][k] + cost[k + 1][j];
+        if c < best.0 { best = (c, Some(k)); }
+      }
+      (cost[i][j], pairing[i][j]) = best;
+    }
+  }
+
+  // Edits by token: inserted before, replacement (None when deleted), inserted after.
+  // Closers of the outermost block go after the whole line
+  let mut prefix = vec![String::new(); n];
+  let mut replacement: Vec<Option<char>> = tokens.iter().map(|&t| Some(chars[t])).collect();
+  let mut suffix = vec![String::new(); n];
+  let mut trailing = String::new();
+  let mut blocks = vec![(0, n)];
+  while let Some((i, j)) = blocks.pop() {
+    if i >= j { continue }
+    let a = chars[tokens[i]];
+    match pairing[i][j] {
+      None => {
+        if let Some(close) = delimiters.closing_delimiter(a) {
+          if j == n { trailing.insert(0, close) } else { suffix[j - 1].insert(0, close) }
+        }
+        else if let Some(open) = delimiters.opening_delimiter(a) { prefix[i].push(open); }
+        else { replacement[i] = None; }
+        blocks.push((i + 1, j));
+      },
+      Some(k) => {
+        let b = chars[tokens[k]];
+        if let Some(close) = delimiters.closing_delimiter(a) { replacement[k] = Some(close); }
+        else if let Some(open) = delimiters.opening_delimiter(b) { replacement[i] = Some(open); }
+        else {
+          let (open, close) = delimiters.pairs[0];
+          (replacement[i], replacement[k]) = (Some(open), Some(close));
+        }
+        blocks.push((i + 1, k));
+        blocks.push((k + 1, j));
+      },
+    }
   }
+
+  let mut token = 0;
+  let mut line: String = chars.iter().enumerate().map(|(pos, &c)| {
+    if tokens.get(token) != Some(&pos) { return c.to_string() }
+    token += 1;
+    let t = token - 1;
+    format!("{}{}{}", prefix[t], replacement[t].map(String::from).unwrap_or_default(), suffix[t])
+  }).collect();
+  // A dangling escape would make the closing quote literal: it's dropped
+  if escaped { line.pop(); }
+  line.extend(quote);
+  line += &trailing;
+  Repair { line, edits: cost[0][n] + quote.map_or(0, |_| 1) + escaped as usize }
+}
+
+type Score = u64;
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum ScoreError {
+  /// Character missing from the score table
+  Unscored(char),
+  /// Completion whose score doesn't fit a `Score`
+  Overflow(String),
+  /// Sum of the scores that doesn't fit a `Score`
+  SumOverflow,
 }
 
-fn corrupted_score<R: Borrow<ParseResult>>(results: impl Iterator<Item=R>) -> u64 {
-  results.map(|r| match r.borrow() {
-    ParseResult::Corrupted(')') => 3,
-    ParseResult::Corrupted(']') => 57,
-    ParseResult::Corrupted('}') => 1197,
-    ParseResult::Corrupted('>') => 25137,
-    _ => 0
-  }).sum()
+/// Points by closer.
+/// - base: multiplier applied to the score of a completion before adding each next closer
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct ScoreTable { points: Vec<(char, Score)>, base: Score }
+
+impl ScoreTable {
+  fn new(points: &[(char, Score)], base: Score) -> Self { ScoreTable { points: points.to_vec(), base } }
+
+  fn corrupted() -> Self { ScoreTable::new(&[(')', 3), (']', 57), ('}', 1197), ('>', 25137)], 1) }
+  fn incomplete() -> Self { ScoreTable::new(&[(')', 1), (']', 2), ('}', 3), ('>', 4)], 5) }
+
+  fn points(&self, c: char) -> Result<Score, ScoreError> {
+    self.points.iter().find(|&&(p, _)| p == c).map(|&(_, pts)| pts).ok_or(ScoreError::Unscored(c))
+  }
+
+  fn completion_points(&self, completion: &str) -> Result<Score, ScoreError> {
+    completion.chars().try_fold(0, |score: Score, c| {
+      let pts = self.points(c)?;
+      score.checked_mul(self.base).and_then(|s| s.checked_add(pts)).ok_or_else(|| ScoreError::Overflow(completion.to_string()))
+    })
+  }
+
+  /// Points of each corrupted line
+  fn corrupted_scores<R: Borrow<ParseResult>>(&self, results: impl Iterator<Item=R>) -> Result<Vec<Score>, ScoreError> {
+    results.filter_map(|r| r.borrow().corrupting_char().map(|c| self.points(c))).collect()
+  }
+
+  /// Points of each incomplete line
+  fn incomplete_scores<R: Borrow<ParseResult>>(&self, results: impl Iterator<Item=R>) -> Result<Vec<Score>, ScoreError> {
+    results.filter_map(|r| r.borrow().completion().map(|c| self.completion_points(c))).collect()
+  }
 }
 
-fn incomplete_score<R: Borrow<ParseResult>>(results: impl Iterator<Item=R>) -> u64 {
-  fn pt(c: char) -> u64 { match c { ')' => 1, ']' => 2, '}' => 3, '>' => 4, _ => panic!("Unexpected character") } }
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Aggregation { Sum, Mean, Median }
 
-  let mut scores = results.filter_map(|r| match r.borrow() {
-    ParseResult::Incomplete(completion) => Some(completion.chars().fold(0u64,|sum, c| sum*5 + pt(c))),
-    _ => None
-  }).collect_vec();
+impl Aggregation {
+  /// **returns**: the sum, 0 when there's no score. The mean and the median, None when there's no score.
+  /// The mean, and the median of an even number of scores (mean of the 2 middle ones), are rounded down.
+  fn apply(&self, scores: &[Score]) -> Result<Option<Score>, ScoreError> {
+    match self {
+      Aggregation::Sum => scores.iter().try_fold(0 as Score, |sum, &s| sum.checked_add(s)).map(Some).ok_or(ScoreError::SumOverflow),
+      _ if scores.is_empty() => Ok(None),
+      // Summed as u128, the mean itself lies within the scores range
+      Aggregation::Mean => Ok(Some((scores.iter().map(|&s| s as u128).sum::<u128>() / scores.len() as u128) as Score)),
+      Aggregation::Median => {
+        let sorted = scores.iter().copied().sorted_unstable().collect_vec();
+        let (lo, hi) = (sorted[(sorted.len() - 1)/2], sorted[sorted.len()/2]);
+        Ok(Some(lo + (hi - lo)/2))
+      },
+    }
+  }
+}
 
-  scores.rdxsort();
+/// Sum of the corrupted lines points, 0 if there's none
+fn corrupted_score<R: Borrow<ParseResult>>(results: impl Iterator<Item=R>) -> Result<Score, ScoreError> {
+  Ok(Aggregation::Sum.apply(&ScoreTable::corrupted().corrupted_scores(results)?)?.unwrap_or(0))
+}
 
-  scores[scores.len()/2]
+/// Median of the incomplete lines points, None if there's none
+fn incomplete_score<R: Borrow<ParseResult>>(results: impl Iterator<Item=R>) -> Result<Option<Score>, ScoreError> {
+  Aggregation::Median.apply(&ScoreTable::incomplete().incomplete_scores(results)?)
 }
 
 
@@ -82,14 +440,184 @@ use super::*;
       "[<>({}){}[([])<>]]",
       "(((((((((())))))))))",
     ];
-    for l in VALID_LINES { assert_eq!(parse_line(l), Valid); }
+    for l in VALID_LINES { assert_eq!(parse_line(l, &DelimiterSet::puzzle()), Valid); }
   }
 
   #[test]
   fn test_parse_line_corrupted() {
     const CORRUPTED_LINES: [&str; 4] = [ "(]", "{()()()>", "(((()))}", "<([]){()}[{}])" ];
     const CORRUPTING_CHARS: [char ; 4] = [']', '>', '}', ')'];
-    for (l, c) in CORRUPTED_LINES.iter().zip(CORRUPTING_CHARS) { assert_eq!(parse_line(l), Corrupted(c)); }
+    for (l, c) in CORRUPTED_LINES.iter().zip(CORRUPTING_CHARS) { assert_eq!(parse_line(l, &DelimiterSet::puzzle()).corrupting_char(), Some(c)); }
+  }
+
+  #[test]
+  fn test_parse_line_positions() {
+    let puzzle = DelimiterSet::puzzle();
+    assert_eq!(
+      parse_line("{([(<{}[<>[]}>{[]{[(<()>", &puzzle),
+      Corrupted { found: '}', column: 13, expected: Some(']'), opener: Some(8) }
+    );
+    assert_eq!(parse_line("())", &puzzle), Corrupted { found: ')', column: 3, expected: None, opener: None });
+    assert_eq!(
+      parse_line("[({(<(())[]>[[{[]{<()<>>", &puzzle),
+      Incomplete { completion: "}}]])})]".to_string(), openers: vec![18, 15, 14, 13, 4, 3, 2, 1] }
+    );
+  }
+
+  #[test]
+  fn test_diagnostic() {
+    let puzzle = DelimiterSet::puzzle();
+    let diagnose = |l: &str| parse_line(l, &puzzle).diagnose(l).to_string();
+
+    assert_eq!(diagnose("{([(<{}[<>[]}>{[]{[(<()>"), "\
+{([(<{}[<>[]}>{[]{[(<()>
+       -    ^ expected ']' to close '[' at column 8, found '}' at column 13");
+    assert_eq!(diagnose("())"), "())\n  ^ unexpected ')' at column 3");
+    assert_eq!(diagnose("[(<>"), "[(<>\n--  ^ incomplete, missing \")]\"");
+    assert_eq!(diagnose("[]"), "[]\nvalid");
+  }
+
+  #[test]
+  fn test_bracket_parser() {
+    let puzzle = DelimiterSet::puzzle();
+    let mut parser = BracketParser::new(&puzzle);
+    assert_eq!((parser.depth(), parser.expected_closer()), (0, None));
+
+    assert_eq!(parser.feed("{([(<{}[<"), None);
+    assert_eq!((parser.depth(), parser.expected_closer()), (7, Some('>')));
+    assert_eq!(parser.push('>'), None);
+    assert_eq!(parser.feed("[]"), None);
+    assert_eq!((parser.depth(), parser.expected_closer()), (6, Some(']')));
+
+    // Reported once, as soon as it occurs
+    let corrupted = Corrupted { found: '}', column: 13, expected: Some(']'), opener: Some(8) };
+    assert_eq!(parser.push('}'), Some(corrupted.clone()));
+    assert_eq!(parser.feed(">{[]{[(<()>"), None);
+    assert_eq!(parser.finish(), corrupted);
+
+    parser.reset();
+    assert_eq!(parser.feed("[(<>"), None);
+    assert_eq!(parser.finish(), Incomplete { completion: ")]".to_string(), openers: vec![2, 1] });
+  }
+
+  #[test]
+  fn test_parse_lines() {
+    let puzzle = DelimiterSet::puzzle();
+    let stream = "()\r\n[<>({}){}[([])<>]]\n(]]]]\n\n{<\n((";
+    let results = parse_lines(stream.chars().map(Ok), &puzzle).map(Result::unwrap).collect_vec();
+    assert_eq!(results, vec![
+      Valid,
+      Valid,
+      Corrupted { found: ']', column: 2, expected: Some(')'), opener: Some(1) },
+      Valid,
+      Incomplete { completion: ">}".to_string(), openers: vec![2, 1] },
+      Incomplete { completion: "))".to_string(), openers: vec![2, 1] },
+    ]);
+
+    // A corrupted last line is reported once, w/ or w/o trailing newline
+    assert_eq!(parse_lines("()\n(]]".chars().map(Ok), &puzzle).count(), 2);
+    assert_eq!(parse_lines("()\n(]]\n".chars().map(Ok), &puzzle).count(), 2);
+
+    // The corruption comes out before the end of its line is read
+    let mut results = parse_lines("(]".chars().chain(std::iter::repeat(']')).map(Ok), &puzzle);
+    assert_eq!(results.next().unwrap().unwrap().corrupting_char(), Some(']'));
+
+    let guillemets = DelimiterSet::new(&[('«', '»'), ('(', ')')]);
+    let from_reader = parse_lines(read_chars("«(»)\n".as_bytes()), &guillemets);
+    assert_eq!(from_reader.map(|r| r.unwrap().corrupting_char()).collect_vec(), vec![Some('»')]);
+  }
+
+  #[test]
+  fn test_repair_line() {
+    let puzzle = DelimiterSet::puzzle();
+    let repair = |l: &str| { let Repair { line, edits } = repair_line(l, &puzzle); (line, edits) };
+    assert_eq!(repair("[<>({}){}[([])<>]]"), ("[<>({}){}[([])<>]]".to_string(), 0));
+    assert_eq!(repair("(]"), ("()".to_string(), 1));
+    assert_eq!(repair("())"), ("()()".to_string(), 1));
+    assert_eq!(repair("((a"), ("(())".to_string(), 2));
+    assert_eq!(repair("{()()()>"), ("{()()()}".to_string(), 1));
+    assert_eq!(repair("]["), ("[][]".to_string(), 2));
+    assert_eq!(repair("<([]){()}[{}])"), ("<([]){()}[{}]>".to_string(), 1));
+
+    let code = DelimiterSet::code();
+    assert_eq!(repair_line("let x = (a + b];", &code), Repair { line: "let x = (a + b);".to_string(), edits: 1 });
+    assert_eq!(repair_line("f(g(x)", &code), Repair { line: "f(g(x))".to_string(), edits: 1 });
+
+    // Delimiters within quotes are neither matched nor edited
+    assert_eq!(repair_line(r#"f("(")"#, &code), Repair { line: r#"f("(")"#.to_string(), edits: 0 });
+    assert_eq!(repair_line(r#"f("(""#, &code), Repair { line: r#"f("(")"#.to_string(), edits: 1 });
+    assert_eq!(repair_line(r#"f("(]"#, &code), Repair { line: r#"f("(]")"#.to_string(), edits: 2 });
+    assert_eq!(repair_line(r#"g('\'', "\"(" ]"#, &code), Repair { line: r#"g('\'', "\"(" )"#.to_string(), edits: 1 });
+    for l in [r#"f("(")"#, r#"f("(""#, r#"{"a": [1, "}"]"#, r#"x = '[' + "]"; y = (["#, r#"s("\")", ']'"#, r#""(\"#] {
+      let repaired = repair_line(l, &code).line;
+      assert_eq!(parse_line(&repaired, &code), Valid, "{} repaired as {}", l, repaired);
+    }
+    assert_eq!(repair_line(r#"("\"#, &code), Repair { line: r#"("")"#.to_string(), edits: 3 });
+
+    for l in SAMPLE.split('\n') {
+      let Repair { line, edits } = repair_line(l, &puzzle);
+      assert_eq!(parse_line(&line, &puzzle), Valid, "{} repaired as {}", l, line);
+      match parse_line(l, &puzzle) {
+        // Turning openers into closers may beat the completion
+        Incomplete { completion, .. } => assert!(edits <= completion.len()),
+        _ => assert!(edits >= 1),
+      }
+    }
+  }
+
+  #[test]
+  fn test_scoring() {
+    let results = SAMPLE.split('\n').map(|l| parse_line(l, &DelimiterSet::puzzle())).collect_vec();
+    let incomplete = ScoreTable::incomplete().incomplete_scores(results.iter()).unwrap();
+    assert_eq!(incomplete, vec![288957, 5566, 1480781, 995444, 294]);
+    assert_eq!(Aggregation::Sum.apply(&incomplete), Ok(Some(2771042)));
+    assert_eq!(Aggregation::Mean.apply(&incomplete), Ok(Some(554208)));
+    assert_eq!(Aggregation::Median.apply(&incomplete), Ok(Some(288957)));
+
+    // Even number of scores, and no score
+    assert_eq!(Aggregation::Median.apply(&[4, 1, 10, 3]), Ok(Some(3)));
+    assert_eq!(Aggregation::Mean.apply(&[1, 2]), Ok(Some(1)));
+    assert_eq!(Aggregation::Sum.apply(&[]), Ok(Some(0)));
+    assert_eq!(Aggregation::Mean.apply(&[]), Ok(None));
+    assert_eq!(Aggregation::Median.apply(&[]), Ok(None));
+    assert_eq!(incomplete_score(results.iter().filter(|r| r.completion().is_none())), Ok(None));
+    assert_eq!(corrupted_score(results.iter().filter(|r| r.corrupting_char().is_none())), Ok(0));
+
+    // Overflows
+    let huge = [Score::MAX, Score::MAX - 2, 3];
+    assert_eq!(Aggregation::Sum.apply(&huge), Err(ScoreError::SumOverflow));
+    assert_eq!(Aggregation::Mean.apply(&huge), Ok(Some(Score::MAX / 3 * 2)));
+    assert_eq!(Aggregation::Median.apply(&huge[..2]), Ok(Some(Score::MAX - 1)));
+
+    // Other tables
+    let code = DelimiterSet::code();
+    let results = ["(a]", "(a", "[\"a"].map(|l| parse_line(l, &code));
+    assert_eq!(ScoreTable::incomplete().incomplete_scores(results.iter()), Err(ScoreError::Unscored('"')));
+    let table = ScoreTable::new(&[(')', 1), (']', 2), ('"', 3)], 10);
+    assert_eq!(table.corrupted_scores(results.iter()), Ok(vec![2]));
+    assert_eq!(table.incomplete_scores(results.iter()), Ok(vec![1, 32]));
+    assert_eq!(ScoreTable::incomplete().completion_points(&">".repeat(40)), Err(ScoreError::Overflow(">".repeat(40))));
+  }
+
+  #[test]
+  fn test_parse_line_delimiter_sets() {
+    let code = DelimiterSet::code();
+    assert_eq!(parse_line(r#"fn main() { let v = vec![1, 2]; println!("{}", v[0] < 3); }"#, &code), Valid);
+    assert_eq!(parse_line(r#"{"a": [1, "}"], "b": '\'(' }"#, &code), Valid);
+    assert_eq!(parse_line(r#"if (a[0] > b) { "("#, &code).completion(), Some(r#""}"#));
+    assert_eq!(parse_line("let x = (a + b];", &code).corrupting_char(), Some(']'));
+
+    // Other characters corrupt the line, unless ignored
+    let puzzle = DelimiterSet::puzzle();
+    assert_eq!(parse_line("(a)", &puzzle).corrupting_char(), Some('a'));
+    assert_eq!(parse_line("( )", &puzzle.clone().with_ignored(&[' '])), Valid);
+    assert_eq!(parse_line("(a b)", &puzzle.clone().with_ignored(&[' '])).corrupting_char(), Some('a'));
+    assert_eq!(parse_line("(a b)", &puzzle.ignoring_others()), Valid);
+
+    // Custom pairs and quotes
+    let latex = DelimiterSet::new(&[('{', '}'), ('[', ']')]).with_quotes(&[('$', '$')]).ignoring_others();
+    assert_eq!(parse_line(r"\frac{a}{b} $\{ x $ [c]", &latex), Valid);
+    assert_eq!(parse_line(r"\frac{a}{b $", &latex).completion(), Some("$}"));
   }
 
   const SAMPLE: &str = 
@@ -106,29 +634,32 @@ use super::*;
 
   #[test]
   fn test_part1_sample() {
-    let results = SAMPLE.split('\n').map(parse_line).collect_vec();
+    let results = SAMPLE.split('\n').map(|l| parse_line(l, &DelimiterSet::puzzle())).collect_vec();
     let expected = vec![
-      Incomplete("}}]])})]".to_string()),
-      Incomplete(")}>]})".to_string()),
-      Corrupted('}'),
-      Incomplete("}}>}>))))".to_string()),
-      Corrupted(')'),
-      Corrupted(']'),
-      Incomplete("]]}}]}]}>".to_string()),
-      Corrupted(')'),
-      Corrupted('>'),
-      Incomplete("])}>".to_string()),
+      (None, Some("}}]])})]")),
+      (None, Some(")}>]})")),
+      (Some('}'), None),
+      (None, Some("}}>}>))))")),
+      (Some(')'), None),
+      (Some(']'), None),
+      (None, Some("]]}}]}]}>")),
+      (Some(')'), None),
+      (Some('>'), None),
+      (None, Some("])}>")),
     ];
-    assert_eq!(results, expected);
-    assert_eq!(corrupted_score(results.iter()), 26397);
-    assert_eq!(incomplete_score(results.iter()), 288957);
+    assert_eq!(results.iter().map(|r| (r.corrupting_char(), r.completion())).collect_vec(), expected);
+    assert_eq!(corrupted_score(results.iter()), Ok(26397));
+    assert_eq!(incomplete_score(results.iter()), Ok(Some(288957)));
   }
 
   #[test]
   fn part1() {
-    let results = common::parse::read_lines("../input/day10.txt").map(parse_line).collect_vec();
-    let corrupted =  corrupted_score(results.iter());
-    let incomplete = incomplete_score(results.iter());
+    let results = common::parse::read_lines("../input/day10.txt").map(|l| parse_line(l, &DelimiterSet::puzzle())).collect_vec();
+    let file = std::fs::File::open("../input/day10.txt").unwrap();
+    let streamed = parse_lines(read_chars(file), &DelimiterSet::puzzle()).map(Result::unwrap).collect_vec();
+    assert_eq!(streamed, results);
+    let corrupted =  corrupted_score(results.iter()).unwrap();
+    let incomplete = incomplete_score(results.iter()).unwrap().unwrap();
     println!("day10 part1 answer = {}", corrupted);
     println!("day10 part1 answer = {}", incomplete);
 
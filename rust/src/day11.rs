@@ -1,64 +1,164 @@
-use std::collections::VecDeque;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use itertools::iproduct;
 use ndarray::Array2;
 
-trait Adjacents {
-  type Index;
-  /// **ANSME**: 
-  ///   - How return a generic Iterator (e.g. impl Iterator<Item=Index>)
-  ///   - How return a generic Iterator w/ references or mutable references (e.g. impl Iterator<Item=&mut T>)
-  fn get_adjacents(&self, i: Self::Index) -> Vec<Self::Index>;
+use crate::common::automaton::{self, Boundary, Rule};
+use crate::common::parse::{self, DigitGridParseError};
+use crate::common::render::{self, Rgb};
+
+pub fn part1_and_2() {
+  let mut octopuses = Octopuses::from_file("../input/day11.txt").unwrap();
+
+  octopuses.run_until(|o| o.nb_steps == 100, 100);
+  println!("day11 part1 answer = {}", octopuses.nb_flashes);
+
+  let synchronized = octopuses.first_synchronized_step(10_000).expect("Octopuses should synchronize");
+  println!("day11 part2 answer = {}", synchronized);
 }
 
-impl Adjacents for Array2<u8> {
-    type Index = (usize, usize);
+/// Plays the simulation in the terminal, until all octopuses flash or after `nb_steps`.
+/// frames: directory where each step is also saved as a PPM image
+pub fn animate(nb_steps: Option<usize>, delay: Duration, frames: Option<&Path>) -> io::Result<()> {
+  const FRAME_SCALE: usize = 8;
+  let mut octopuses = Octopuses::from_file("../input/day11.txt")?;
+  let save_frame = |octopuses: &Octopuses| match frames {
+    Some(dir) => fs::write(dir.join(format!("day11_{:04}.ppm", octopuses.nb_steps)), octopuses.render_ppm(FRAME_SCALE)),
+    None => Ok(()),
+  };
 
-    fn get_adjacents(&self, (i, j): Self::Index) -> Vec<Self::Index> {
-      let (nb_rows, nb_cols) = self.dim();
-      let up    = if i > 0            { i - 1 } else { 0 };
-      let down  = if i < nb_rows - 1  { i + 1 } else { i };
-      let left  = if j > 0            { j - 1 } else { 0 };
-      let right = if j < nb_cols - 1  { j + 1 } else { j };
+  if let Some(dir) = frames { fs::create_dir_all(dir)?; }
+  save_frame(&octopuses)?;
+  print!("{}{}", render::ANSI_CLEAR, octopuses.render_ansi(0));
 
-      iproduct!(up..=down, left..=right).collect()
-    }
+  for _ in 0..nb_steps.unwrap_or(10_000) {
+    thread::sleep(delay);
+    let nb_flashes = octopuses.step();
+    save_frame(&octopuses)?;
+    print!("{}{}", render::ANSI_HOME, octopuses.render_ansi(nb_flashes));
+    io::stdout().flush()?;
+    if nb_steps.is_none() && octopuses.is_synchronized() { break; }
+  }
+  Ok(())
+}
+
+/// Energy of every octopus increases by 1 each step. Octopuses above 9 flash, once per step: their energy drops to 0
+/// and their neighbours' increases by 1.
+struct Octopus;
+
+impl Rule for Octopus {
+  type Cell = u8;
+
+  fn update(&self, &energy: &u8, _neighbours: &[&u8]) -> u8 { energy + 1 }
+  fn fires(&self, &energy: &u8) -> bool { energy > 9 }
+  fn fire(&self, energy: &mut u8) { *energy = 0; }
+  fn receive(&self, energy: &mut u8) { *energy += 1; }
 }
 
 /// **returns**: number of lighten octopuses
-fn step(energies: &mut Array2<u8>) -> usize {
-  type Index = (usize, usize);
-  let mut to_visit: VecDeque<Index> = VecDeque::new();
-  let mut nb_lightened: usize = 0;
-
-  // INFO: Started w/ a closure, but rust prevents borrowing a mutable reference twice (on to_visit)
-  macro_rules! increase_energy { ($ij:ident, $e:ident) => { { 
-      *$e = (*$e + 1) % 10;
-      if *$e == 0 { 
-        nb_lightened += 1;
-        to_visit.push_back($ij); 
+fn step(energies: &mut Array2<u8>) -> usize { automaton::step(&Octopus, energies) }
+
+/// States repeat every `period` steps, from step `start` on
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Cycle { start: usize, period: usize, state: Array2<u8> }
+
+/// Octopuses energies w/ the number of steps and flashes so far
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Octopuses { energies: Array2<u8>, boundary: Boundary<u8>, nb_steps: usize, nb_flashes: usize }
+
+impl Octopuses {
+  fn from_file(filename: impl AsRef<Path>) -> Result<Self, DigitGridParseError> {
+    parse::read_digit_grid(filename).map(Octopuses::new)
+  }
+
+  fn new(energies: Array2<u8>) -> Self { Octopuses { energies, boundary: Boundary::Bounded, nb_steps: 0, nb_flashes: 0 } }
+
+  fn with_boundary(self, boundary: Boundary<u8>) -> Self { Octopuses { boundary, ..self } }
+
+  /// **returns**: number of flashes during the step
+  fn step(&mut self) -> usize {
+    let nb_flashes = automaton::step_with(&Octopus, &mut self.energies, &self.boundary);
+    self.nb_steps += 1;
+    self.nb_flashes += nb_flashes;
+    nb_flashes
+  }
+
+  /// Flashed during the last step
+  fn has_flashed(&self, ij: (usize, usize)) -> bool { self.nb_steps > 0 && self.energies[ij] == 0 }
+
+  const FLASH: Rgb = Rgb(255, 255, 160);
+
+  /// Dark to light blue w/ the energy
+  fn energy_colour(energy: u8) -> Rgb { Rgb(10 * energy, 12 + 16 * energy, 60 + 18 * energy) }
+
+  /// Header w/ the step and its number of flashes, then the energies, flashing octopuses highlighted
+  fn render_ansi(&self, nb_flashes: usize) -> String {
+    let mut rendered = format!("step {:>4}: {:>3} flashes\n", self.nb_steps, nb_flashes);
+    for (i, row) in self.energies.rows().into_iter().enumerate() {
+      for (j, &energy) in row.iter().enumerate() {
+        rendered += &if self.has_flashed((i, j)) {
+          format!("{}{}\x1b[1m{}{}", render::ansi_bg(Self::FLASH), render::ansi_fg(Rgb(0, 0, 0)), energy, render::ANSI_RESET)
+        } else {
+          format!("{}{}{}", render::ansi_fg(Self::energy_colour(energy)), energy, render::ANSI_RESET)
+        };
       }
-  } } }
-
-  // 1. increase energy by 1 for all
-  for (ij, e) in energies.indexed_iter_mut() { increase_energy!(ij, e); }
-  // println!("to_visit: {:?}", to_visit);
-  // println!("After Increase\n{:?}", energies);
-
-  while let Some(ij) = to_visit.pop_front() {
-    let center = energies[ij];
-    // println!("Visiting {:?} w/ value {}", ij, center);
-    debug_assert_eq!(center, 0, "Visit only lighten octopuses");
-    for ij in energies.get_adjacents(ij) {
-      let e = energies.get_mut(ij).unwrap();
-      if *e > 0 { increase_energy!(ij, e) }
+      rendered.push('\n');
     }
+    rendered
+  }
+
+  /// PPM image, each octopus drawn as a `scale` x `scale` square
+  fn render_ppm(&self, scale: usize) -> Vec<u8> {
+    let pixels = Array2::from_shape_fn(self.energies.dim(), |ij| {
+      if self.has_flashed(ij) { Self::FLASH } else { Self::energy_colour(self.energies[ij]) }
+    });
+    render::ppm(&pixels, scale)
   }
 
-  // println!("to_visit: {:?}", to_visit);
-  // println!("visited {:?} w/ value {}\n{:?}", ij, center, energies);
+  /// All octopuses flashed during the last step
+  fn is_synchronized(&self) -> bool { self.nb_steps > 0 && self.energies.iter().all(|&e| e == 0) }
 
-  nb_lightened
+  /// Steps until the predicate holds, checked after each step
+  /// **returns**: the step it held at, None if it still doesn't after `max_steps` more steps
+  fn run_until(&mut self, mut predicate: impl FnMut(&Octopuses) -> bool, max_steps: usize) -> Option<usize> {
+    for _ in 0..max_steps {
+      self.step();
+      if predicate(self) { return Some(self.nb_steps) }
+    }
+    None
+  }
+
+  /// First step where all octopuses flash together
+  fn first_synchronized_step(&mut self, max_steps: usize) -> Option<usize> {
+    self.run_until(Octopuses::is_synchronized, max_steps)
+  }
+
+  /// Steps until a state repeats, storing each state met on the way
+  /// **returns**: None if no state repeats within `max_steps` more steps
+  fn find_cycle(&mut self, max_steps: usize) -> Option<Cycle> {
+    let mut seen: HashMap<Array2<u8>, usize> = HashMap::new();
+    seen.insert(self.energies.clone(), self.nb_steps);
+    for _ in 0..max_steps {
+      self.step();
+      if let Some(&start) = seen.get(&self.energies) {
+        return Some(Cycle { start, period: self.nb_steps - start, state: self.energies.clone() })
+      }
+      seen.insert(self.energies.clone(), self.nb_steps);
+    }
+    None
+  }
+}
+
+
+/// One row of energy levels 0-9 per line, blank lines are skipped
+impl FromStr for Octopuses {
+  type Err = DigitGridParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> { parse::digit_grid(s).map(Octopuses::new) }
 }
 
 
@@ -103,7 +203,7 @@ mod test {
 
   #[test]
   fn test_step100() {
-    let mut energies: Array2<u8> = array![ 
+    let energies: Array2<u8> = array![ 
       [ 5,4,8,3,1,4,3,2,2,3 ],
       [ 2,7,4,5,8,5,4,7,1,1 ],
       [ 5,2,6,4,5,5,6,1,7,3 ],
@@ -129,49 +229,100 @@ mod test {
       [ 6,7,8,9,9,9,8,7,6,6 ],
     ];
 
-    let nb_lightened: usize = (1..=100).into_iter().map(|_| step(&mut energies)).sum();
+    let mut octopuses = Octopuses::new(energies);
+    assert_eq!(octopuses.run_until(|o| o.nb_steps == 100, 1000), Some(100));
 
-    assert_eq!(energies, expected100);
-    assert_eq!(nb_lightened, 1656);
+    assert_eq!(octopuses.energies, expected100);
+    assert_eq!(octopuses.nb_flashes, 1656);
 
     // Test all flash
-    let mut nb_steps: usize = 100;
-    loop {
-      nb_steps += 1;
-      step(&mut energies);
-      if energies.iter().all(|&e| e == 0) { break; }
-    }
+    assert_eq!(octopuses.first_synchronized_step(1000), Some(195));
 
-    assert_eq!(nb_steps, 195);
+    // Synchronized octopuses flash every 10 steps
+    let cycle = octopuses.find_cycle(1000).unwrap();
+    assert_eq!((cycle.start, cycle.period), (195, 10));
+    assert!(cycle.state.iter().all(|&e| e == 0));
   }
 
   #[test]
-  fn part1_and_2() {
+  fn test_boundaries() {
+    let energies: Array2<u8> = array![
+      [ 9, 0, 0, 0 ],
+      [ 0, 0, 0, 0 ],
+      [ 0, 0, 0, 0 ],
+    ];
 
-    let mut energies: Array2<u8> = array![ 
-      [ 5,4,2,1,4,5,1,7,4,1 ],
-      [ 3,8,7,7,3,2,1,5,6,8 ],
-      [ 7,5,8,3,2,7,3,8,6,4 ],
-      [ 3,4,5,1,7,1,7,7,7,8 ],
-      [ 2,6,5,1,6,1,5,1,5,6 ],
-      [ 6,3,7,7,1,6,7,5,2,6 ],
-      [ 5,1,8,2,8,5,2,8,3,1 ],
-      [ 4,7,6,6,8,5,6,6,7,6 ],
-      [ 3,4,3,7,1,8,7,5,8,3 ],
-      [ 3,6,3,3,3,7,1,5,8,6 ],
-    ]; 
-    let nb_lightened: usize = (1..=100).into_iter().map(|_| step(&mut energies)).sum();
-    println!("day11 part 1 answer= {}", nb_lightened);
-    assert_eq!(nb_lightened, 1673);
+    let mut bounded = Octopuses::new(energies.clone());
+    assert_eq!(bounded.step(), 1);
+    assert_eq!(bounded.energies, array![[0, 2, 1, 1], [2, 2, 1, 1], [1, 1, 1, 1]]);
 
-    // Test all flash
-    let mut nb_steps: usize = 100;
-    loop {
-      nb_steps += 1;
-      step(&mut energies);
-      if energies.iter().all(|&e| e == 0) { break; }
-    }
+    let mut torus = Octopuses::new(energies.clone()).with_boundary(Boundary::Torus);
+    assert_eq!(torus.step(), 1);
+    assert_eq!(torus.energies, array![[0, 2, 1, 2], [2, 2, 1, 2], [2, 2, 1, 2]]);
+
+    // The padding never flashes, nor gets energy
+    let mut padded = Octopuses::new(energies).with_boundary(Boundary::Padded(9));
+    assert_eq!(padded.step(), 1);
+    assert_eq!(padded.energies, bounded.energies);
+  }
 
+  #[test]
+  fn test_run_until_capped() {
+    let energies: Array2<u8> = array![[5, 4, 8], [2, 7, 4], [5, 2, 6]];
+    let mut octopuses = Octopuses::new(energies.clone());
+    assert_eq!(octopuses.first_synchronized_step(3), None);
+    assert_eq!(octopuses.nb_steps, 3);
+    assert_eq!(octopuses.run_until(|o| o.nb_flashes > 0, 0), None);
+
+    let mut octopuses = Octopuses::new(energies);
+    let synchronized = octopuses.first_synchronized_step(1000).unwrap();
+    let cycle = octopuses.find_cycle(1000).unwrap();
+    assert_eq!((cycle.start, cycle.period), (synchronized, 10));
+  }
+
+  #[test]
+  fn test_from_str() {
+    let octopuses: Octopuses = "123\n456\n\n789 \n".parse().unwrap();
+    assert_eq!(octopuses.energies, array![[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
+    assert_eq!(Octopuses::from_file("../input/day11_sample.txt").unwrap().energies.dim(), (10, 10));
+
+    assert_eq!(Octopuses::from_file("../input/day11_missing.txt"), Err(DigitGridParseError::Io(io::ErrorKind::NotFound)));
+    assert_eq!(io::Error::from(DigitGridParseError::Io(io::ErrorKind::NotFound)).kind(), io::ErrorKind::NotFound);
+    assert_eq!(io::Error::from(DigitGridParseError::Empty).kind(), io::ErrorKind::InvalidData);
+
+    assert_eq!("12\n3a".parse::<Octopuses>(), Err(DigitGridParseError::InvalidChar { row: 2, column: 2, c: 'a' }));
+    assert_eq!("12\n-1".parse::<Octopuses>(), Err(DigitGridParseError::InvalidChar { row: 2, column: 1, c: '-' }));
+    assert_eq!("12\n345".parse::<Octopuses>(), Err(DigitGridParseError::RaggedRow { row: 2, nb_columns: 3, expected: 2 }));
+    assert_eq!(" \n".parse::<Octopuses>(), Err(DigitGridParseError::Empty));
+  }
+
+  #[test]
+  fn test_render() {
+    let mut octopuses = Octopuses::new(array![[9, 1], [1, 1]]);
+    let nb_flashes = octopuses.step();
+    let rendered = octopuses.render_ansi(nb_flashes);
+    println!("{}", rendered);
+    let lines = rendered.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "step    1:   1 flashes");
+    assert!(lines[1].starts_with(&format!("{}{}\x1b[1m0", render::ansi_bg(Octopuses::FLASH), render::ansi_fg(Rgb(0, 0, 0)))));
+    assert!(lines[1].ends_with(&format!("{}3{}", render::ansi_fg(Octopuses::energy_colour(3)), render::ANSI_RESET)));
+
+    let ppm = octopuses.render_ppm(1);
+    let header = "P6\n2 2\n255\n";
+    assert!(ppm.starts_with(header.as_bytes()));
+    assert_eq!(&ppm[header.len()..header.len() + 3], &[255, 255, 160]);
+  }
+
+  #[test]
+  fn part1_and_2() {
+    let mut octopuses = Octopuses::from_file("../input/day11.txt").unwrap();
+    octopuses.run_until(|o| o.nb_steps == 100, 100);
+    println!("day11 part 1 answer= {}", octopuses.nb_flashes);
+    assert_eq!(octopuses.nb_flashes, 1673);
+
+    // Test all flash
+    let nb_steps = octopuses.first_synchronized_step(10_000).unwrap();
     println!("day11 part 2 answer= {}", nb_steps);
+    assert_eq!(nb_steps, 279);
   }
 }
@@ -4,79 +4,271 @@ use std::collections::HashSet;
 use std::convert::AsRef;
 use std::fmt::Display;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::common;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Clone, Copy)]
 struct Pos(i32, i32);
 impl Pos {
-  fn from_str(s: impl AsRef<str>) -> Self {
-    let (x, y) = s.as_ref().split_once(',').unwrap();
-    Pos(x.parse::<i32>().unwrap(), y.parse::<i32>().unwrap())
-  }
-
   fn max(&self, &Pos(i1, j1): &Self) -> Self {
     let &Pos(i0, j0) = self;
     Pos(i0.max(i1), j0.max(j1))
   }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PosParseError {
+  MissingComma,
+  /// Coordinate which isn't a non-negative integer
+  InvalidNumber(&'static str),
+}
+
+impl FromStr for Pos {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (x, y) = s.split_once(',').ok_or(PosParseError::MissingComma)?;
+    let parse = |v: &str, axis| v.trim().parse::<i32>().ok().filter(|&v| v >= 0).ok_or(PosParseError::InvalidNumber(axis));
+    Ok(Pos(parse(x, "x")?, parse(y, "y")?))
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum Instruction {
   X(i32),
   Y(i32)
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InstructionParseError {
+  /// Not a `fold along x=<n>` nor a `fold along y=<n>`
+  InvalidFormat,
+  /// Fold line too large for the coordinates
+  InvalidNumber,
+}
+
+impl FromStr for Instruction {
+  type Err = InstructionParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (_, axis, value) = regex_captures!(r"^fold along ([xy])=(\d+)$", s.trim()).ok_or(InstructionParseError::InvalidFormat)?;
+    let value = value.parse::<i32>().map_err(|_| InstructionParseError::InvalidNumber)?;
+    Ok( if axis == "x" { Instruction::X(value) } else { Instruction::Y(value) } )
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FoldError {
+  /// The dot lies further from the fold line than the paper on the other side.
+  /// dot: as given to the fold, i.e. before the whole sequence of instructions when compiled into a `Folding`
+  NegativeCoordinate { instruction: Instruction, dot: Pos },
+  /// The dot lies off the paper before any fold
+  NegativeInput { dot: Pos },
+}
+
 impl Instruction {
-  fn from_str(s: impl AsRef<str>) -> Self {
-    let (_, axis, value) = regex_captures!(r"^fold along ([x,y])=(\d+)$", s.as_ref()).unwrap();
-    let value = value.parse::<i32>().unwrap();
-    match axis {
-      "x" => Instruction::X(value),
-      "y" => Instruction::Y(value),
-      _ => panic!("Unexpected. Axis should be either x or y"),
+  fn fold<I>(&self, dots: I) -> Result<HashSet<Pos>, FoldError> where I: IntoIterator<Item=Pos> {
+    dots.into_iter().map(|dot| {
+      if dot.0 < 0 || dot.1 < 0 { return Err(FoldError::NegativeInput { dot }) }
+      let folded = match (*self, dot) {
+        (Instruction::Y(row), Pos(i, j)) if j > row => Pos(i, 2*row - j),
+        (Instruction::X(col), Pos(i, j)) if i > col => Pos(2*col - i, j),
+        _ => dot,
+      };
+      if folded.0 < 0 || folded.1 < 0 { Err(FoldError::NegativeCoordinate { instruction: *self, dot }) } else { Ok(folded) }
+    }).collect()
+  }
+}
+
+/// Coordinates in [start, end] are mapped to `v + offset`, or `-v + offset` when reflected.
+/// invalid: (index, instruction) of the first fold mapping these coordinates below 0
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Piece { start: i64, end: i64, reflected: bool, offset: i64, invalid: Option<(usize, Instruction)> }
+
+impl Piece {
+  fn image(&self, v: i64) -> i64 { if self.reflected { self.offset - v } else { v + self.offset } }
+
+  /// **returns**: the sub-pieces whose image is <= `t`, and > `t`
+  fn split_image(&self, t: i64) -> (Option<Piece>, Option<Piece>) {
+    let sub_piece = |start: i64, end: i64| if start <= end { Some(Piece { start, end, ..*self }) } else { None };
+    if self.reflected {
+      let first_below = self.offset - t;
+      (sub_piece(self.start.max(first_below), self.end), sub_piece(self.start, self.end.min(first_below - 1)))
+    } else {
+      let last_below = t - self.offset;
+      (sub_piece(self.start, self.end.min(last_below)), sub_piece(self.start.max(last_below + 1), self.end))
+    }
+  }
+}
+
+/// Successive folds along one axis, as a piecewise reflection of the coordinates 0..=i32::MAX
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct AxisMap { pieces: Vec<Piece> }
+
+impl AxisMap {
+  fn identity() -> Self {
+    AxisMap { pieces: vec![ Piece { start: 0, end: i32::MAX as i64, reflected: false, offset: 0, invalid: None } ] }
+  }
+
+  /// Maps coordinates beyond `line` back onto the other side
+  fn then_fold(&mut self, line: i32, index: usize, instruction: Instruction) {
+    let line = line as i64;
+    let mut pieces = Vec::with_capacity(self.pieces.len() + 2);
+    for piece in self.pieces.drain(..) {
+      if piece.invalid.is_some() { pieces.push(piece); continue; }
+      let (kept, beyond) = piece.split_image(line);
+      pieces.extend(kept);
+      if let Some(beyond) = beyond {
+        let reflected = Piece { reflected: !beyond.reflected, offset: 2*line - beyond.offset, ..beyond };
+        let (negative, folded) = reflected.split_image(-1);
+        pieces.extend(folded);
+        pieces.extend(negative.map(|p| Piece { invalid: Some((index, instruction)), ..p }));
+      }
     }
+    pieces.sort_unstable_by_key(|p| p.start);
+    self.pieces = pieces;
   }
 
-  fn fold<I>(&self, dots: I) -> HashSet<Pos> where I: IntoIterator<Item=Pos> {
-    match self {
-      &Instruction::Y(row) => dots
-        .into_iter()
-        .map(|Pos(i, j)| if j > row { Pos(i, 2*row - j) } else { Pos(i, j) } )
-        .inspect(|&Pos(i, j)| assert!(i >= 0 && j >= 0, "Fold should always produce positive values") )
-        .collect(),
-      &Instruction::X(col) => dots
-        .into_iter()
-        .map(|Pos(i, j)| if i > col { Pos(2*col - i, j) } else { Pos(i, j) } )
-        .inspect(|&Pos(i, j)| assert!(i >= 0 && j >= 0, "Fold should always produce positive values") )
-        .collect(),
+  /// v: within 0..=i32::MAX, the caller rejects negative coordinates
+  fn apply(&self, v: i32) -> Result<i32, (usize, Instruction)> {
+    debug_assert!(v >= 0, "negative coordinate {}", v);
+    let v = v as i64;
+    let piece = &self.pieces[self.pieces.partition_point(|p| p.start <= v) - 1];
+    match piece.invalid {
+      Some(invalid) => Err(invalid),
+      None => Ok(piece.image(v) as i32),
     }
   }
 }
 
+/// Sequence of instructions compiled into one mapping per axis, as folding along x never moves y and vice versa
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Folding { x: AxisMap, y: AxisMap }
+
+impl Folding {
+  fn compile<'a>(instructions: impl IntoIterator<Item=&'a Instruction>) -> Self {
+    let mut folding = Folding { x: AxisMap::identity(), y: AxisMap::identity() };
+    for (index, &instruction) in instructions.into_iter().enumerate() {
+      match instruction {
+        Instruction::X(col) => folding.x.then_fold(col, index, instruction),
+        Instruction::Y(row) => folding.y.then_fold(row, index, instruction),
+      }
+    }
+    folding
+  }
+
+  fn apply(&self, dot: Pos) -> Result<Pos, FoldError> {
+    let Pos(i, j) = dot;
+    if i < 0 || j < 0 { return Err(FoldError::NegativeInput { dot }) }
+    match (self.x.apply(i), self.y.apply(j)) {
+      (Ok(i), Ok(j)) => Ok(Pos(i, j)),
+      // Reported for the first fold going negative
+      (Err(invalid), Ok(_)) | (Ok(_), Err(invalid)) => Err(invalid),
+      (Err(x), Err(y)) => Err(if x.0 < y.0 { x } else { y }),
+    }.map_err(|(_, instruction)| FoldError::NegativeCoordinate { instruction, dot })
+  }
+
+  fn fold<I>(&self, dots: I) -> Result<HashSet<Pos>, FoldError> where I: IntoIterator<Item=Pos> {
+    dots.into_iter().map(|dot| self.apply(dot)).collect()
+  }
+}
+
+/// line_no: 1-based
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FoldParseError {
+  InvalidDot { line_no: usize, error: PosParseError },
+  InvalidInstruction { line_no: usize, error: InstructionParseError },
+}
+
 
 #[derive(PartialEq, Eq, Debug)]
 struct FoldInput { dots: Vec<Pos>, instructions: Vec<Instruction> }
 
 impl FoldInput {
-  fn from_file(filename: impl AsRef<Path>) -> Self {
-    let mut lines = common::parse::read_lines(filename);
+  fn from_file(filename: impl AsRef<Path>) -> Result<Self, FoldParseError> {
+    Self::from_lines(common::parse::read_lines(filename))
+  }
+
+  /// Dots up to the first blank line, then instructions
+  fn from_lines<S: AsRef<str>>(lines: impl Iterator<Item=S>) -> Result<Self, FoldParseError> {
+    let mut lines = (1..).zip(lines);
     let dots: Vec<Pos> = lines.by_ref()
-      .take_while( |s| ! s.is_empty() )
-      .map( Pos::from_str )
-      .collect();
+      .take_while( |(_, s)| ! s.as_ref().trim().is_empty() )
+      .map( |(line_no, s)| s.as_ref().parse().map_err(|error| FoldParseError::InvalidDot { line_no, error }) )
+      .collect::<Result<_, _>>()?;
 
     let instructions: Vec<Instruction> = lines
-      .filter( |s| ! s.is_empty() )
-      .map( Instruction::from_str )
-      .collect();
+      .filter( |(_, s)| ! s.as_ref().trim().is_empty() )
+      .map( |(line_no, s)| s.as_ref().parse().map_err(|error| FoldParseError::InvalidInstruction { line_no, error }) )
+      .collect::<Result<_, _>>()?;
 
-    FoldInput { dots, instructions }
+    Ok(FoldInput { dots, instructions })
   }
 }
 
+/// Standard AoC capital letters: 4 columns x 6 rows, drawn w/ `#` and `.`, rows concatenated
+const FONT: [(char, &str); 16] = [
+  ('A', ".##.#..##..######..##..#"),
+  ('B', "###.#..####.#..##..####."),
+  ('C', ".##.#..##...#...#..#.##."),
+  ('E', "#####...###.#...#...####"),
+  ('F', "#####...###.#...#...#..."),
+  ('G', ".##.#..##...#.###..#.###"),
+  ('H', "#..##..######..##..##..#"),
+  ('J', "..##...#...#...##..#.##."),
+  ('K', "#..##.#.##..#.#.#.#.#..#"),
+  ('L', "#...#...#...#...#...####"),
+  ('O', ".##.#..##..##..##..#.##."),
+  ('P', "###.#..##..####.#...#..."),
+  ('R', "###.#..##..####.#.#.#..#"),
+  ('S', ".####...#....##....####."),
+  ('U', "#..##..##..##..##..#.##."),
+  ('Z', "####...#..#..#..#...####"),
+];
+const GLYPH_WIDTH: i32 = 4;
+const GLYPH_HEIGHT: i32 = 6;
+/// Glyphs are separated by an empty column
+const GLYPH_SPACING: i32 = GLYPH_WIDTH + 1;
+
+#[derive(PartialEq, Eq, Debug)]
+enum OcrError {
+  /// Dots below the 6 rows of a line of letters
+  TooTall { nb_rows: i32 },
+  /// position: 0-based index of the glyph in the line
+  /// glyph: drawn as in `FONT`, the separating column included when it isn't empty
+  UnknownGlyph { position: usize, glyph: String },
+}
+
 #[derive(Shrinkwrap)]
 struct Dots(Vec<Pos>);
 
+impl Dots {
+  /// Reads the dots as a line of `FONT` letters, starting from column 0
+  fn ocr(&self) -> Result<String, OcrError> {
+    let Pos(max_cols, max_rows) = self.iter().fold(Pos(0, 0), |max_pos, dot| dot.max(&max_pos) );
+    if max_rows >= GLYPH_HEIGHT { return Err(OcrError::TooTall { nb_rows: max_rows + 1 }) }
+    let dots: HashSet<Pos> = self.iter().copied().collect();
+
+    (0..=(max_cols / GLYPH_SPACING) as usize).map(|position| {
+      let first_col = position as i32 * GLYPH_SPACING;
+      let draw = |cols: std::ops::Range<i32>| -> String {
+        (0..GLYPH_HEIGHT)
+          .flat_map(|j| cols.clone().map(move |i| Pos(i, j)))
+          .map(|pos| if dots.contains(&pos) { '#' } else { '.' })
+          .collect()
+      };
+      let glyph = draw(first_col..first_col + GLYPH_WIDTH);
+      let separator_empty = !draw(first_col + GLYPH_WIDTH..first_col + GLYPH_SPACING).contains('#');
+      FONT.iter()
+        .find(|&&(_, g)| separator_empty && g == glyph)
+        .map(|&(c, _)| c)
+        .ok_or_else(|| OcrError::UnknownGlyph {
+          position,
+          glyph: if separator_empty { glyph } else { draw(first_col..first_col + GLYPH_SPACING) },
+        })
+    }).collect()
+  }
+}
+
 impl Display for Dots {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
       let Pos(max_cols, max_rows) = self.iter().fold(Pos(0, 0), |max_pos, dot| dot.max(&max_pos) );
@@ -95,12 +287,13 @@ impl Display for Dots {
 #[cfg(test)]
 mod test {
   use itertools::Itertools;
+  use crate::common::random::Lcg;
 
 use super::*;
 
   #[test]
   fn test_parse_file() {
-    let fold_input = FoldInput::from_file("../input/day13_sample.txt");
+    let fold_input = FoldInput::from_file("../input/day13_sample.txt").unwrap();
     let expected = FoldInput {
       dots: vec![
         Pos(6,10), Pos(0,14), Pos(9,10), Pos(0,3), Pos(10,4), Pos(4,11), Pos(6,0), Pos(6,12), Pos(4,1), Pos(0,13),
@@ -112,27 +305,142 @@ use super::*;
 
   #[test]
   fn test_fold() {
-    let FoldInput { dots, .. } = FoldInput::from_file("../input/day13_sample.txt");
-    let folded: Vec<Pos> = Instruction::Y(7).fold(dots).into_iter().sorted().collect();
+    let FoldInput { dots, .. } = FoldInput::from_file("../input/day13_sample.txt").unwrap();
+    let folded: Vec<Pos> = Instruction::Y(7).fold(dots).unwrap().into_iter().sorted().collect();
     let expected = vec![
       Pos(0,0), Pos(0,1), Pos(0,3), Pos(1,4), Pos(2,0), Pos(3,0), Pos(3,4), Pos(4,1), Pos(4,3), Pos(6,0), Pos(6,2),
       Pos(6,4), Pos(8,4), Pos(9,0), Pos(9,4), Pos(10,2), Pos(10,4),
     ];
     assert_eq!(folded, expected);
+
+    // Folding more than half of the paper
+    assert_eq!(
+      Instruction::X(2).fold(vec![Pos(1, 0), Pos(5, 1)]),
+      Err(FoldError::NegativeCoordinate { instruction: Instruction::X(2), dot: Pos(5, 1) })
+    );
+  }
+
+  /// Folds one instruction at a time
+  fn fold_sequentially(dots: Vec<Pos>, instructions: &[Instruction]) -> Result<HashSet<Pos>, FoldError> {
+    instructions.iter().try_fold(dots.into_iter().collect(), |dots: HashSet<Pos>, instruction| instruction.fold(dots))
+  }
+
+  #[test]
+  fn test_folding() {
+    let FoldInput { dots, instructions } = FoldInput::from_file("../input/day13.txt").unwrap();
+    let folding = Folding::compile(&instructions);
+    assert_eq!(folding.fold(dots.clone()), fold_sequentially(dots, &instructions));
+
+    // x=5 then x=2: 0 1 2 3 4 _ 6 7 8 9 10 -> 0 1 2 1 0 _ 0 1 2 1 0. The first fold line lands at -1, as do 11 and beyond
+    let folding = Folding::compile(&[Instruction::X(5), Instruction::X(2)]);
+    let folded = (0..=10).filter(|&i| i != 5).map(|i| folding.apply(Pos(i, 0)).unwrap().0).collect_vec();
+    assert_eq!(folded, vec![0, 1, 2, 1, 0, 0, 1, 2, 1, 0]);
+    assert!(folding.apply(Pos(5, 0)).is_err() && folding.apply(Pos(11, 0)).is_err());
+    assert_eq!(folding.x.pieces.len(), 6);
+
+    // Errors report the first fold going negative, whatever the axis
+    let instructions = [Instruction::Y(1), Instruction::X(1), Instruction::Y(0)];
+    assert_eq!(
+      Folding::compile(&instructions).apply(Pos(3, 3)),
+      Err(FoldError::NegativeCoordinate { instruction: Instruction::Y(1), dot: Pos(3, 3) })
+    );
+    assert_eq!(
+      Folding::compile(&instructions).apply(Pos(3, 1)),
+      Err(FoldError::NegativeCoordinate { instruction: Instruction::X(1), dot: Pos(3, 1) })
+    );
+
+    // Dots off the paper are rejected before any fold
+    let instructions = [Instruction::X(5)];
+    for dot in [Pos(-1, 0), Pos(0, -1), Pos(i32::MIN, i32::MIN)] {
+      assert_eq!(Folding::compile(&instructions).apply(dot), Err(FoldError::NegativeInput { dot }));
+      assert_eq!(fold_sequentially(vec![dot], &instructions), Err(FoldError::NegativeInput { dot }));
+    }
+    assert_eq!(Folding::compile(&[]).apply(Pos(-1, 0)), Err(FoldError::NegativeInput { dot: Pos(-1, 0) }));
+  }
+
+  #[test]
+  fn test_folding_random() {
+    let mut lcg = Lcg::new(0x2021_0013);
+    let mut next = |bound: u64| lcg.next(bound) as i32;
+
+    for _ in 0..200 {
+      let dots = (0..1 + next(50)).map(|_| Pos(next(200), next(200))).collect_vec();
+      let instructions = (0..1 + next(12))
+        .map(|_| if next(2) == 0 { Instruction::X(next(120)) } else { Instruction::Y(next(120)) })
+        .collect_vec();
+      let sequential = fold_sequentially(dots.clone(), &instructions);
+      match Folding::compile(&instructions).fold(dots.clone()) {
+        Ok(folded) => assert_eq!(Ok(folded), sequential, "{:?} {:?}", dots, instructions),
+        // Both fail, though maybe for distinct dots
+        Err(_) => assert!(sequential.is_err(), "{:?} {:?}", dots, instructions),
+      }
+    }
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    let parse = |s: &str| FoldInput::from_lines(s.lines());
+    assert_eq!(
+      parse("1,2\n\nfold along x=1\n\nfold along y=3\n"),
+      Ok(FoldInput { dots: vec![Pos(1, 2)], instructions: vec![Instruction::X(1), Instruction::Y(3)] })
+    );
+    assert_eq!(parse("1,2\n3 4"), Err(FoldParseError::InvalidDot { line_no: 2, error: PosParseError::MissingComma }));
+    assert_eq!(parse("1,-2"), Err(FoldParseError::InvalidDot { line_no: 1, error: PosParseError::InvalidNumber("y") }));
+    assert_eq!(parse("a,2"), Err(FoldParseError::InvalidDot { line_no: 1, error: PosParseError::InvalidNumber("x") }));
+
+    let invalid_instruction = |line_no, error| Err(FoldParseError::InvalidInstruction { line_no, error });
+    assert_eq!(parse("1,2\n\nfold along ,=3"), invalid_instruction(3, InstructionParseError::InvalidFormat));
+    assert_eq!(parse("1,2\n\nfold along x=1\nfold along z=3"), invalid_instruction(4, InstructionParseError::InvalidFormat));
+    assert_eq!(parse("1,2\n\nfold along y=99999999999"), invalid_instruction(3, InstructionParseError::InvalidNumber));
   }
 
   #[test]
   fn part1() {
-    let FoldInput { dots, instructions } = FoldInput::from_file("../input/day13.txt");
-    let nb_folded = instructions[0].fold(dots).len();
+    let FoldInput { dots, instructions } = FoldInput::from_file("../input/day13.txt").unwrap();
+    let nb_folded = instructions[0].fold(dots).unwrap().len();
     println!("Day 13 answer part 1 = {}", nb_folded);
     assert_eq!(nb_folded, 704);
   }
 
   #[test]
   fn part2() {
-    let FoldInput { dots, instructions } = FoldInput::from_file("../input/day13.txt");
-    let folded = instructions.into_iter().fold(dots, |dots, instruction| { instruction.fold(dots).into_iter().collect() });
-    println!("{}", Dots(folded));
+    let FoldInput { dots, instructions } = FoldInput::from_file("../input/day13.txt").unwrap();
+    let folded: Vec<Pos> = Folding::compile(&instructions).fold(dots).unwrap().into_iter().collect();
+    let folded = Dots(folded);
+    println!("{}", folded);
+    let letters = folded.ocr();
+    println!("Day 13 answer part 2 = {:?}", letters);
+    assert_eq!(letters, Ok("HGAJBEHC".to_string()));
+  }
+
+  /// Dots of a drawing made of `#`, rows separated by `\n`
+  fn draw(drawing: &str) -> Dots {
+    Dots(drawing.lines().enumerate()
+      .flat_map(|(j, row)| row.chars().positions(|c| c == '#').map(move |i| Pos(i as i32, j as i32)))
+      .collect())
+  }
+
+  #[test]
+  fn test_ocr() {
+    let hello = draw("\
+#..#.####.#....#.....##.
+#..#.#....#....#....#..#
+####.###..#....#....#..#
+#..#.#....#....#....#..#
+#..#.#....#....#....#..#
+#..#.####.####.####..##.");
+    assert_eq!(hello.ocr(), Ok("HELLO".to_string()));
+
+    // Every letter of the font
+    let alphabet = FONT.iter().map(|&(c, _)| c).collect::<String>();
+    let drawing = (0..6).map(|j| FONT.iter().map(|(_, g)| &g[j*4..j*4 + 4]).join(".")).join("\n");
+    assert_eq!(draw(&drawing).ocr(), Ok(alphabet));
+
+    // Unknown, or w/o separating column
+    let unknown = draw("#..#\n.##.\n.##.\n#..#\n#..#\n#..#");
+    assert_eq!(unknown.ocr(), Err(OcrError::UnknownGlyph { position: 0, glyph: "#..#.##..##.#..##..##..#".to_string() }));
+    let glued = draw("####.\n#....\n###..\n#....\n#....\n#...#");
+    assert_eq!(glued.ocr(), Err(OcrError::UnknownGlyph { position: 0, glyph: "####.#....###..#....#....#...#".to_string() }));
+    assert_eq!(draw("#\n\n\n\n\n\n#").ocr(), Err(OcrError::TooTall { nb_rows: 7 }));
   }
 }
\ No newline at end of file
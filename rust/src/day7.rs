@@ -1,114 +1,183 @@
-use rdxsort::*;
-use conv::*;
 use itertools::Itertools;
 
 use super::common::math::delta;
 use super::common::math::sum_n;
 
-type PosType = u16;
+type PosType = u32;
+type Weight = u64;
+type Cost = u128;
 
-pub(self) struct Crabs { positions: Vec<PosType> }
+/// positions: (position, number of crabs at that position)
+#[derive(Debug)]
+pub(self) struct Crabs { positions: Vec<(PosType, Weight)> }
 impl Crabs {
-  pub fn displace<F>(&self, cost_fn: F) -> u32 where F: Fn(PosType) -> u32 { self.positions.iter().map(|&p| cost_fn(p)).sum() }
-  pub fn sorted(&mut self) { self.positions.rdxsort(); }
+  pub fn new(positions: Vec<PosType>) -> Crabs { Crabs { positions: positions.into_iter().map(|p| (p, 1)).collect() } }
+  pub fn weighted(positions: impl IntoIterator<Item=(PosType, Weight)>) -> Crabs {
+    Crabs { positions: positions.into_iter().filter(|&(_, w)| w > 0).collect() }
+  }
+
+  pub fn total_weight(&self) -> Weight { self.positions.iter().map(|&(_, w)| w).sum() }
+  pub fn displace<F>(&self, cost_fn: F) -> Cost where F: Fn(PosType) -> Cost {
+    self.positions.iter().map(|&(p, w)| Cost::from(w) * cost_fn(p)).sum()
+  }
+
+  /// Sorts by position, merging crabs sharing the same position
+  pub fn sorted(&mut self) {
+    self.positions.sort_unstable();
+    self.positions = self.positions.iter().copied().coalesce(|(p0, w0), (p1, w1)| {
+      if p0 == p1 { Ok((p0, w0 + w1)) } else { Err(((p0, w0), (p1, w1))) }
+    }).collect();
+  }
+
+  /// Lowest position where the cumulative weight exceeds half of the total weight.
+  /// For an even total, any point between the 2 medians constitute the minimal plateau.
+  pub fn weighted_median(&mut self) -> Option<PosType> {
+    self.sorted();
+    let half = self.total_weight() / 2;
+    self.positions.iter()
+      .scan(0, |cumulative, &(p, w)| { *cumulative += w; Some((p, *cumulative)) })
+      .find(|&(_, cumulative)| cumulative > half)
+      .map(|(p, _)| p)
+  }
+
+  pub fn displace_to<M: CostModel + ?Sized>(&self, x: PosType, model: &M) -> Cost { self.displace(|p| model.cost(delta(x, p))) }
 }
 
-/// Strategy here is to compute the displacement at the median.
-/// The cost displacement function is of the form |x - c|
-/// When aggregating |x - c| functions, in can be shown that the minimum is at the median region
-/// of all aggregate functions.
-pub(self) fn min_displace_part1(crabs: &mut Crabs) -> u32 {
-    if crabs.positions.is_empty() { return 0; }
-    crabs.sorted();
+/// Cost for a single crab to move by `distance`.
+/// Implementors must be convex and non-decreasing w/ the distance. The aggregated cost over all crabs is then also
+/// convex, with its minimum lying between the leftmost and rightmost crab.
+pub(self) trait CostModel {
+  fn cost(&self, distance: PosType) -> Cost;
+
+  /// Minimal aggregated displacement. Defaults to a ternary search, models w/ a known shortcut override it.
+  fn min_displace(&self, crabs: &mut Crabs) -> Cost { ternary_search(crabs, self) }
+}
 
-    let n = crabs.positions.len();
-    // For an even number of crabs (absolute functions) any point between the 2 medians constitute
-    // the minimal plateau. Thus we just pick the median at the odd index.
-    let median = crabs.positions[n / 2];
+/// |x - p|, i.e. part 1
+pub(self) struct Linear;
+/// ∑ i for i = 0..|x-p|, i.e. part 2
+pub(self) struct Triangular;
+/// |x - p|^2
+pub(self) struct Quadratic;
+/// Any user-defined convex cost
+pub(self) struct Convex<F: Fn(PosType) -> Cost>(F);
+
+impl CostModel for Linear {
+  fn cost(&self, distance: PosType) -> Cost { distance.into() }
+  fn min_displace(&self, crabs: &mut Crabs) -> Cost { min_displace_part1(crabs) }
+}
+
+impl CostModel for Triangular {
+  fn cost(&self, distance: PosType) -> Cost { sum_n(distance.into()) }
+  fn min_displace(&self, crabs: &mut Crabs) -> Cost { min_displace_part2(crabs) }
+}
 
-    crabs.displace(|p| delta(median, p) as u32)
+impl CostModel for Quadratic {
+  fn cost(&self, distance: PosType) -> Cost { Cost::from(distance).pow(2) }
+}
+
+impl<F: Fn(PosType) -> Cost> CostModel for Convex<F> {
+  fn cost(&self, distance: PosType) -> Cost { (self.0)(distance) }
+}
+
+/// Integer ternary search over [min position, max position].
+/// As the aggregated cost is convex, when both probes are equal a minimum necessarily lies between them, so plateaus
+/// are handled by narrowing to [m0, m1].
+pub(self) fn ternary_search<M: CostModel + ?Sized>(crabs: &Crabs, model: &M) -> Cost {
+  let (mut lo, mut hi) = match crabs.positions.iter().map(|&(p, _)| p).minmax().into_option() {
+    None => return 0,
+    Some((lo, hi)) => (lo, hi),
+  };
+
+  while hi - lo > 2 {
+    let third = (hi - lo) / 3;
+    let (m0, m1) = (lo + third, hi - third);
+    let (c0, c1) = (crabs.displace_to(m0, model), crabs.displace_to(m1, model));
+    if c0 < c1 { hi = m1 - 1; }
+    else if c0 > c1 { lo = m0 + 1; }
+    else { lo = m0; hi = m1; }
+  }
+
+  (lo..=hi).map(|x| crabs.displace_to(x, model)).min().unwrap()
+}
+
+/// Strategy here is to compute the displacement at the (weighted) median.
+/// The cost displacement function is of the form w|x - c|
+/// When aggregating w|x - c| functions, in can be shown that the minimum is at the weighted median region
+/// of all aggregate functions.
+pub(self) fn min_displace_part1(crabs: &mut Crabs) -> Cost {
+  match crabs.weighted_median() {
+    None => 0,
+    Some(median) => crabs.displace_to(median, &Linear),
+  }
 }
 
 ///
 /// cost function = ∑ i for i = 0..|x-p|
 ///       c(x, p) = (|x-p|)(|x-p| + 1) / 2
-///               = ( x^2 -2px +p^2 + |x-p| )/ 2
-///
-/// derivative of cost function
-/// c(x,p)' = x - p - 1/2   for [...p[
-///         = x - p + 1/2   for [p..[
 ///
-/// sum of aggregated cost functions
-/// ∑ c' = nx - ∑ p + [(n-i)*(-1/2) + i*1/2]
-///      = nx -  P  + [-n/2 + i]
-///      = nx -  (P+n/2) + i
-///      = nx - C + i
-/// 1st term nx increases with x
-/// 2nd term P-n/2 is constant
-/// 3rd i term is step-wise increasing with x
-/// Thus derivative is ever increasing
+/// Instead of the continuous derivative, use the forward difference Δc(x, p) = c(x+1, p) - c(x, p), which stays in
+/// the integers:
+/// Δc(x, p) = x - p + 1   for p <= x   (moving away from p)
+///          = x - p       for p >  x   (moving toward p)
 ///
-/// Can be shown through the 2nd derivative as well.
-/// ∑ c'' = n  // Positive curvature
+/// sum of aggregated differences, w/ w the weight of each position, W = ∑ w and k(x) the number of crabs at or left of x
+/// ∑ wΔc = Wx - ∑ wp + k(x)
+///       = Wx -  P   + k(x)
+/// 1st term Wx increases with x
+/// 2nd term P is constant
+/// 3rd term k(x) is step-wise increasing with x
+/// Thus the difference is non-decreasing: the aggregated cost is convex.
 ///
-/// There's only one minimum, we just need to scan through positions to find out where the derivative
-/// goes from negative to positive.
+/// The minimum is at the smallest x where ∑ Δc >= 0, found by binary search over [min position, max position].
+/// k(x) is a binary search in the cumulative weights of the sorted positions. Everything is computed exactly in i128.
 ///
 /// Examples in graph: https://www.desmos.com/calculator/7i48ybrrz5
-/// The aggregated cost
-pub(self) fn min_displace_part2(crabs: &mut Crabs) -> u32 {
-  fn cost(a: PosType, b: PosType) -> u32 { sum_n(delta(a, b) as u32) }
-  fn total_cost(crabs: &Crabs, a: PosType) -> u32 { crabs.displace(|b| cost(a, b)) }
-
+pub(self) fn min_displace_part2(crabs: &mut Crabs) -> Cost {
   if crabs.positions.is_empty() { return 0; }
   crabs.sorted();
 
-  let n: f64 = crabs.positions.len() as f64;
   #[allow(non_snake_case)]
-  let P: f64 = crabs.positions.iter().map(|&p| f64::from(p)).sum();
+  let W = i128::from(crabs.total_weight());
   #[allow(non_snake_case)]
-  let C: f64 = P + 0.5*n;
-
-  let d_cost = |x: PosType, i: usize| -> f64 { n * f64::from(x) + f64::value_from(i).unwrap() - C };
-
-  for (i, (&x0, &x1)) in crabs.positions.iter().tuple_windows::<(&PosType,&PosType)>().enumerate() {
-    let d1 = d_cost(x1, i+1); // derivative on the left of x1
-    if d1 < 0.0 { continue; } // function is still decreasing between [x0, x1[
-
-    let d0 = d_cost(x0, i+1); // derivative on the right of x0
-    if d0 >= 0.0 {
-      println!("minimum found at x0 = {}, cost = {}", x0, total_cost(crabs, x0));
-      return total_cost(crabs, x0);
-    } // both derivative are increasing. Return x0
-    else {
-      let x_min: f64 = (C - f64::value_from(i).unwrap()) / n;
-      let x_min0: PosType = x_min.ceil() as PosType;
-      let x_min1: PosType = x_min.floor() as PosType;
-      let c_min0: u32 = total_cost(crabs, x_min0);
-      let c_min1: u32 = total_cost(crabs, x_min1);
-      return c_min0.min(c_min1);
-    }
+  let P: i128 = crabs.positions.iter().map(|&(p, w)| i128::from(p) * i128::from(w)).sum();
+  let cumulative_weights: Vec<i128> = crabs.positions.iter()
+    .scan(0, |cumulative, &(_, w)| { *cumulative += i128::from(w); Some(*cumulative) })
+    .collect();
+
+  let k = |x: PosType| match crabs.positions.partition_point(|&(p, _)| p <= x) {
+    0 => 0,
+    i => cumulative_weights[i - 1],
+  };
+  let d_cost = |x: PosType| W * i128::from(x) - P + k(x);
+
+  // d_cost(last) = W*last - P + W > 0, so the search always terminates within the range
+  let (mut lo, mut hi) = (crabs.positions[0].0, crabs.positions.last().unwrap().0);
+  while lo < hi {
+    let mid = lo + (hi - lo) / 2;
+    if d_cost(mid) >= 0 { hi = mid; } else { lo = mid + 1; }
   }
 
-  let &last = crabs.positions.last().unwrap();
-  total_cost(crabs, last)
+  crabs.displace_to(lo, &Triangular)
 }
 
 #[cfg(test)]
 mod test {
   use crate::common;
-  use crate::common::math::delta;
+  use crate::common::math::{delta, sum_n};
+  use crate::common::random::Lcg;
   use crate::day7::{min_displace_part1, min_displace_part2};
-  use super::PosType;
+  use super::{Cost, PosType};
   use super::Crabs;
+  use super::{CostModel, Convex, Linear, Quadratic, Triangular, ternary_search};
 
   const POSITIONS: [PosType; 10] = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
 
   #[test]
   fn test_displace() {
-    let crabs = Crabs { positions: POSITIONS.to_vec() };
+    let crabs = Crabs::new(POSITIONS.to_vec());
 
-    fn cost(a: PosType, b: PosType) -> u32 { delta(a, b) as u32 }
+    fn cost(a: PosType, b: PosType) -> Cost { delta(a, b).into() }
 
     assert_eq!(crabs.displace(|p| cost(1, p)), 41);
     assert_eq!(crabs.displace(|p| cost(2, p)), 37);
@@ -118,29 +187,107 @@ mod test {
 
   #[test]
   fn test_min_displace_part1() {
-    let mut crabs = Crabs { positions: POSITIONS.to_vec() };
+    let mut crabs = Crabs::new(POSITIONS.to_vec());
     assert_eq!(min_displace_part1(&mut crabs), 37);
   }
 
   #[test]
   fn part1() {
     let positions = common::parse::read_comma_separated("../input/day7.txt");
-    let mut crabs = Crabs { positions };
+    let mut crabs = Crabs::new(positions);
     let sln = min_displace_part1(&mut crabs);
     println!("Part1 solution = {}", sln);
   }
 
   #[test]
   fn test_min_displace_part2() {
-    let mut crabs = Crabs { positions: POSITIONS.to_vec() };
+    let mut crabs = Crabs::new(POSITIONS.to_vec());
     assert_eq!(min_displace_part2(&mut crabs), 168);
   }
 
   #[test]
   fn part2() {
     let positions = common::parse::read_comma_separated("../input/day7.txt");
-    let mut crabs = Crabs { positions };
+    let mut crabs = Crabs::new(positions);
     let sln = min_displace_part2(&mut crabs);
     println!("Part2 solution = {}", sln);
   }
+
+  #[test]
+  fn test_ternary_search() {
+    let crabs = Crabs::new(POSITIONS.to_vec());
+    assert_eq!(ternary_search(&crabs, &Linear), 37);
+    assert_eq!(ternary_search(&crabs, &Triangular), 168);
+  }
+
+  #[test]
+  fn test_cost_models() {
+    fn brute_force<M: CostModel>(crabs: &Crabs, model: &M) -> Cost {
+      (0..=16).map(|x| crabs.displace_to(x, model)).min().unwrap()
+    }
+
+    let mut crabs = Crabs::new(POSITIONS.to_vec());
+    assert_eq!(Linear.min_displace(&mut crabs), brute_force(&crabs, &Linear));
+    assert_eq!(Triangular.min_displace(&mut crabs), brute_force(&crabs, &Triangular));
+    assert_eq!(Quadratic.min_displace(&mut crabs), brute_force(&crabs, &Quadratic));
+
+    let cubic = Convex(|d: PosType| Cost::from(d).pow(3));
+    assert_eq!(cubic.min_displace(&mut crabs), brute_force(&crabs, &cubic));
+
+    // Flat cost: every position is a minimum
+    let flat = Convex(|_| 1);
+    assert_eq!(flat.min_displace(&mut crabs), 10);
+  }
+
+  #[test]
+  fn test_ternary_search_empty() {
+    let crabs = Crabs::new(vec![]);
+    assert_eq!(ternary_search(&crabs, &Quadratic), 0);
+  }
+
+  #[test]
+  fn test_min_displace_part2_brute_force() {
+    let mut lcg = Lcg::new(0x2021_0007);
+    let mut next = |bound: u64| lcg.next(bound);
+
+    for _ in 0..200 {
+      let nb_crabs = 1 + next(40) as usize;
+      let spread = 1 + next(2000);
+      let positions = (0..nb_crabs).map(|_| next(spread) as PosType).collect();
+      let mut crabs = Crabs::new(positions);
+
+      let brute_force = (0..=spread as PosType).map(|x| crabs.displace_to(x, &Triangular)).min().unwrap();
+      assert_eq!(min_displace_part2(&mut crabs), brute_force, "positions = {:?}", crabs.positions);
+    }
+  }
+
+  #[test]
+  fn test_min_displace_part2_no_overflow() {
+    // 1000 crabs on each end: ~2^61 per crab, way past u64::MAX in total
+    let mut crabs = Crabs::weighted([(0, 1000), (PosType::MAX, 1000)]);
+    let half = Cost::from(PosType::MAX / 2);
+    let expected = 1000 * sum_n(half) + 1000 * sum_n(half + 1);
+    assert_eq!(min_displace_part2(&mut crabs), expected);
+  }
+
+  #[test]
+  fn test_weighted_median() {
+    assert_eq!(Crabs::new(POSITIONS.to_vec()).weighted_median(), Some(2));
+    assert_eq!(Crabs::weighted([(1, 1), (100, 3)]).weighted_median(), Some(100));
+    assert_eq!(Crabs::weighted([(100, 2), (1, 2)]).weighted_median(), Some(100));
+    assert_eq!(Crabs::weighted([(5, 0)]).weighted_median(), None);
+  }
+
+  #[test]
+  fn test_weighted_matches_expanded() {
+    let weighted = [(16, 1), (1, 2), (2, 3), (0, 1), (4, 1), (7, 1), (14, 1)];
+    assert_eq!(min_displace_part1(&mut Crabs::weighted(weighted)), 37);
+    assert_eq!(min_displace_part2(&mut Crabs::weighted(weighted)), 168);
+    assert_eq!(ternary_search(&Crabs::weighted(weighted), &Quadratic), Quadratic.min_displace(&mut Crabs::new(POSITIONS.to_vec())));
+
+    // Duplicated entries are merged
+    let mut crabs = Crabs::weighted([(2, 1), (1, 2), (2, 2)]);
+    crabs.sorted();
+    assert_eq!(crabs.positions, vec![(1, 2), (2, 3)]);
+  }
 }
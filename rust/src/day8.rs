@@ -1,4 +1,6 @@
-use std::{collections::HashMap, convert::TryInto, path::Path};
+use std::{convert::TryInto, fmt::Debug, path::Path};
+use std::ops::{BitAnd, BitOr, Not, Sub};
+use std::iter::FromIterator;
 use itertools::Itertools;
 use std::convert::AsRef;
 
@@ -17,9 +19,150 @@ const SIGNALS: [Signal; 7] = [
     Signal::G
 ];
 
-type Digit = Vec<Signal>;
+impl Signal {
+    fn to_char(self) -> char { (b'a' + self as u8) as char }
+}
+
+/// Set of lit segments, one bit per `Signal` (bit 0 = a ... bit 6 = g)
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+struct SegmentSet(u8);
+
+impl SegmentSet {
+    const EMPTY: SegmentSet = SegmentSet(0);
+    const ALL: SegmentSet = SegmentSet(0b111_1111);
+
+    fn single(s: Signal) -> SegmentSet { SegmentSet(1 << s as u8) }
+    fn contains(self, s: Signal) -> bool { self.0 & (1 << s as u8) != 0 }
+    fn len(self) -> usize { self.0.count_ones() as usize }
+    fn is_empty(self) -> bool { self.0 == 0 }
+    fn insert(&mut self, s: Signal) { self.0 |= 1 << s as u8; }
+    fn iter(self) -> impl Iterator<Item=Signal> { SIGNALS.into_iter().filter(move |&s| self.contains(s)) }
+
+    /// The only signal of the set, if it contains exactly one
+    fn single_signal(self) -> Option<Signal> {
+        if self.len() == 1 { Some(SIGNALS[self.0.trailing_zeros() as usize]) } else { None }
+    }
+
+    /// All subsets, from the set itself down to the empty set
+    fn subsets(self) -> impl Iterator<Item=SegmentSet> {
+        let mut next = Some(self.0);
+        std::iter::from_fn(move || {
+            let sub = next?;
+            next = if sub == 0 { None } else { Some((sub - 1) & self.0) };
+            Some(SegmentSet(sub))
+        })
+    }
+}
+
+impl BitAnd for SegmentSet {
+    type Output = SegmentSet;
+    fn bitand(self, rhs: Self) -> Self::Output { SegmentSet(self.0 & rhs.0) }
+}
+
+impl BitOr for SegmentSet {
+    type Output = SegmentSet;
+    fn bitor(self, rhs: Self) -> Self::Output { SegmentSet(self.0 | rhs.0) }
+}
+
+/// Set difference
+impl Sub for SegmentSet {
+    type Output = SegmentSet;
+    fn sub(self, rhs: Self) -> Self::Output { SegmentSet(self.0 & !rhs.0) }
+}
+
+/// Complement within the 7 segments
+impl Not for SegmentSet {
+    type Output = SegmentSet;
+    fn not(self) -> Self::Output { SegmentSet::ALL - self }
+}
+
+impl FromIterator<Signal> for SegmentSet {
+    fn from_iter<I: IntoIterator<Item=Signal>>(iter: I) -> Self {
+        let mut set = SegmentSet::EMPTY;
+        for s in iter { set.insert(s); }
+        set
+    }
+}
+
+impl Debug for SegmentSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{{}}}", self.iter().map(Signal::to_char).collect::<String>())
+    }
+}
+
+type Digit = SegmentSet;
 type Rosetta = [Signal; 7];
 
+/// Canonical leds of each digit, indexed by digit
+const DIGITS: [SegmentSet; 10] = [
+    //          gfedcba
+    SegmentSet(0b1110111), // 0
+    SegmentSet(0b0100100), // 1
+    SegmentSet(0b1011101), // 2
+    SegmentSet(0b1101101), // 3
+    SegmentSet(0b0101110), // 4
+    SegmentSet(0b1101011), // 5
+    SegmentSet(0b1111011), // 6
+    SegmentSet(0b0100101), // 7
+    SegmentSet(0b1111111), // 8
+    SegmentSet(0b1101111), // 9
+];
+
+/// Canonical leds of the hexadecimal letters, following `DIGITS`
+const HEX_LETTERS: [SegmentSet; 6] = [
+    //          gfedcba
+    SegmentSet(0b0111111), // A
+    SegmentSet(0b1111010), // b
+    SegmentSet(0b1010011), // C
+    SegmentSet(0b1111100), // d
+    SegmentSet(0b1011011), // E
+    SegmentSet(0b0011011), // F
+];
+
+/// Font of a display: the canonical leds of each symbol, indexed by the symbol's value.
+/// At most 16 symbols, and no 2 symbols share the same leds.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct GlyphTable { glyphs: Vec<SegmentSet> }
+
+impl GlyphTable {
+    fn new(glyphs: Vec<SegmentSet>) -> GlyphTable {
+        assert!(glyphs.len() <= 16, "At most 16 glyphs are supported");
+        assert!(glyphs.iter().all_unique(), "Glyphs must be distinct");
+        GlyphTable { glyphs }
+    }
+
+    fn standard() -> GlyphTable { GlyphTable::new(DIGITS.to_vec()) }
+    fn hexadecimal() -> GlyphTable { GlyphTable::new(DIGITS.iter().chain(&HEX_LETTERS).copied().collect()) }
+
+    /// Alternate font, w/ the upper-left led (b) lit on 7, and the bottom led (g) off on 9
+    fn alternate() -> GlyphTable {
+        GlyphTable::standard()
+            .with_glyph(7, DIGITS[7] | SegmentSet::single(Signal::B))
+            .with_glyph(9, DIGITS[9] - SegmentSet::single(Signal::G))
+    }
+
+    fn with_glyph(mut self, value: u8, leds: SegmentSet) -> GlyphTable {
+        self.glyphs[value as usize] = leds;
+        GlyphTable::new(self.glyphs)
+    }
+
+    fn len(&self) -> usize { self.glyphs.len() }
+    fn value_of(&self, leds: SegmentSet) -> Option<u8> { self.glyphs.iter().position(|&g| g == leds).map(|n| n as u8) }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum WiringError {
+    /// Patterns which do not form any digit under the best wiring found
+    Inconsistent(Vec<Digit>),
+    /// Patterns that cannot be told apart: decoding to the same digit, or to distinct digits under distinct wirings.
+    /// Empty when the wirings only differ on wires no pattern uses.
+    Ambiguous(Vec<Digit>),
+    /// Rosetta mapping 2 wires to the same led
+    NotAPermutation(Rosetta),
+    /// Values of the glyph table not found among the patterns
+    Missing(Vec<u8>),
+}
+
 
 /// The following uses a deterministic way to find the mapping between the wiring -> light-led signals
 /// ```
@@ -41,79 +184,102 @@ type Rosetta = [Signal; 7];
 /// b: 6 occurences in all 10 digits
 /// e: 4 "
 /// f: 9 "
-/// c: find number w/ 2 leds. remove     f
-/// d: "            " 4 leds. remove b c f
-/// a: "            " 3 leds. remove   c f
-/// g: "            " 7 leds. remove a b c d e f
-///    OR remaining led
+/// a, c: 8 occurences, but only c is in the 2 leds digit
+/// d, g: 7 occurences, but only d is in the 4 leds digit
 /// ```
-/// 
-/// **TODO**: 
-/// - Validate that `ten_digits` abide to the structure above. Otherwise it is an invalid input.
-/// - Maybe convert digits representation to u8 or a bitvec
-/// - Flagging / discovering leds could be done w/ & | bit-wise operators
-/// 
-fn signal_rosetta(ten_digits: &[Digit; 10]) -> Rosetta {
-    let led_freqs: HashMap<Signal, u64> = common::collections::freq_count(ten_digits.iter().flatten().copied());
-
-    // Exploratory choice of the moment: Prefer a closure over a macro, to minimize macro usages. 
-    // - Don't know what is the idiomatic way in Rust. Should I prefer a macro or clojure in general for readability
-    //   and debugging? Are macro are harder to debug than closures and vice-versa. 
-    // - Exploring the clojure way at the moment.
-    // Note: I miss being able to create closure w/ the function definition syntax as in Scala, 
-    //       In Rust function  are pure and don't capture a lexical scope (they have to be unstateful).
-    let led_with_freq = |n: u64| { led_freqs.iter().find(|(_, &f)| f == n).expect(format!("Unable to find a led w/ {} occurences", n).as_str()) };
-    let digit_with_leds = |n: usize| { ten_digits.iter().find(|leds| leds.len() == n).expect(format!("Unable to find digit w/ {} leds", n).as_str()) };
-
-    fn remaining_led(digit: &Digit, to_remove: &[Signal]) -> Signal {
-        // Assumes there's only one
-        *digit.iter().find(|led| ! to_remove.iter().contains(led))
-            .expect(format!("Unable remaining led for digit= {:#?}, while removing {:?}", digit, to_remove).as_str())
-    }
-
-    let (&f, _) = led_with_freq(9);
-    let (&e, _) = led_with_freq(4);
-    let (&b, _) = led_with_freq(6);
-
-    let one   = digit_with_leds(2);
-    let c = remaining_led(one, &[f]);
-
-    let four  = digit_with_leds(4);
-    let d = remaining_led(four, &[b, c, f]);
-
-    let seven = digit_with_leds(3);
-    let a = remaining_led(seven, &[c, f]);
-
-    let g = remaining_led(&SIGNALS.to_vec(), &[a, b, c, d, e, f]);
-
-    [a, b, c, d, e, f, g]
-}
- 
-fn translate_digit(digit: &Digit, rosetta: &Rosetta) -> u8 {
-    // Start by matching on length of lens
-    match digit.len() {
-        2 => 1,
-        4 => 4,
-        3 => 7,
-        7 => 8,
-        5 if digit.contains(&rosetta[Signal::E as usize]) => 2,
-        5 if digit.contains(&rosetta[Signal::B as usize]) => 5,
-        5 => 3,
-        6 if !digit.contains(&rosetta[Signal::D as usize]) => 0,
-        6 if !digit.contains(&rosetta[Signal::C as usize]) => 6,
-        6 if !digit.contains(&rosetta[Signal::E as usize]) => 9,
-        _ => panic!("Impossible digit {:#?}", digit)
-    }
-}
-
-fn translate_digits<'a>(digits: impl Iterator<Item=&'a Digit>, rosetta: &Rosetta) -> Vec<u8> {
-    let mut translated = Vec::<u8>::new();
-    for digit in digits { translated.push(translate_digit(digit, &rosetta)); }
-    translated
+/// Generalizing the above, each led has a signature: the sorted lengths of the glyphs lighting it up. The rules are
+/// derived from `glyphs`: the wire w/ the same signature over the patterns is the led. 
+///
+/// When the signatures cannot single out a led (ambiguous font or invalid patterns), the brute-force solver is used
+/// instead, as it names the offending patterns.
+/// The result is always checked w/ `validate_rosetta`.
+fn signal_rosetta(patterns: &[Digit], glyphs: &GlyphTable) -> Result<Rosetta, WiringError> {
+    // Number of glyphs of each length (at most 16 glyphs, so 5 bits each), packed
+    fn signature(s: Signal, sets: &[SegmentSet]) -> u64 {
+        sets.iter().filter(|d| d.contains(s)).fold(0, |acc, d| acc + (1 << (5 * d.len())))
+    }
+
+    let heuristic = || -> Option<Rosetta> {
+        if patterns.len() != glyphs.len() { return None; }
+        let wire_signatures = SIGNALS.map(|w| signature(w, patterns));
+        let leds = SIGNALS.map(|s| {
+            let led_signature = signature(s, &glyphs.glyphs);
+            SIGNALS.into_iter().filter(|&w| wire_signatures[w as usize] == led_signature).collect::<SegmentSet>().single_signal()
+        });
+        leds.iter().all(Option::is_some).then(|| leds.map(Option::unwrap))
+    };
+
+    match heuristic() {
+        Some(rosetta) => validate_rosetta(patterns, &rosetta, glyphs).map(|_| rosetta),
+        None => brute_force_rosetta(patterns, glyphs),
+    }
+}
+
+/// Tries all 7! = 5040 wirings, keeping the one(s) for which `patterns` are exactly the `glyphs`.
+/// On failure, reports the validation error of the wiring decoding the most distinct glyphs.
+fn brute_force_rosetta(patterns: &[Digit], glyphs: &GlyphTable) -> Result<Rosetta, WiringError> {
+    let nb_decoded = |rosetta: &Rosetta| patterns.iter().filter_map(|d| translate_digit(d, rosetta, glyphs).ok()).unique().count();
+
+    let wirings = SIGNALS.into_iter()
+        .permutations(SIGNALS.len())
+        .map(|p| -> Rosetta { p.try_into().unwrap() })
+        .collect_vec();
+    let valid = wirings.iter().filter(|r| validate_rosetta(patterns, r, glyphs).is_ok()).collect_vec();
+
+    match valid.as_slice() {
+        [&rosetta] => Ok(rosetta),
+        [] => {
+            let best = wirings.iter().max_by_key(|r| nb_decoded(r)).unwrap();
+            Err(validate_rosetta(patterns, best, glyphs).unwrap_err())
+        },
+        _ => {
+            let values = valid.iter().map(|r| translate_digits(patterns.iter(), r, glyphs).unwrap()).collect_vec();
+            let ambiguous = patterns.iter().enumerate()
+                .filter(|&(i, _)| values.iter().map(|v| v[i]).unique().count() > 1)
+                .map(|(_, &d)| d)
+                .collect_vec();
+            Err(WiringError::Ambiguous(ambiguous))
+        },
+    }
+}
+
+/// Checks that `rosetta` is a permutation of the wires, and that `patterns` translate to exactly the `glyphs`.
+fn validate_rosetta(patterns: &[Digit], rosetta: &Rosetta, glyphs: &GlyphTable) -> Result<(), WiringError> {
+    if rosetta.iter().copied().collect::<SegmentSet>() != SegmentSet::ALL {
+        return Err(WiringError::NotAPermutation(*rosetta));
+    }
+
+    let inconsistent = patterns.iter().copied().filter(|d| translate_digit(d, rosetta, glyphs).is_err()).collect_vec();
+    if !inconsistent.is_empty() { return Err(WiringError::Inconsistent(inconsistent)); }
+
+    let values = patterns.iter().map(|d| translate_digit(d, rosetta, glyphs).unwrap()).collect_vec();
+    let ambiguous = patterns.iter().zip(&values)
+        .filter(|&(_, v)| values.iter().filter(|&w| w == v).count() > 1)
+        .map(|(&d, _)| d)
+        .collect_vec();
+    if !ambiguous.is_empty() { return Err(WiringError::Ambiguous(ambiguous)); }
+
+    let missing = (0..glyphs.len() as u8).filter(|n| !values.contains(n)).collect_vec();
+    if !missing.is_empty() { return Err(WiringError::Missing(missing)); }
+
+    Ok(())
+}
+
+/// Maps the lit wires back to the canonical leds
+fn unscramble(digit: &Digit, rosetta: &Rosetta) -> SegmentSet {
+    SIGNALS.into_iter().filter(|&s| digit.contains(rosetta[s as usize])).collect()
+}
+
+fn translate_digit(digit: &Digit, rosetta: &Rosetta, glyphs: &GlyphTable) -> Result<u8, WiringError> {
+    glyphs.value_of(unscramble(digit, rosetta)).ok_or_else(|| WiringError::Inconsistent(vec![*digit]))
+}
+
+fn translate_digits<'a>(digits: impl Iterator<Item=&'a Digit>, rosetta: &Rosetta, glyphs: &GlyphTable) -> Result<Vec<u8>, WiringError> {
+    digits.map(|digit| translate_digit(digit, rosetta, glyphs)).collect()
 }
 
 fn parse_digit(s: &str) -> Digit {
-    s.chars().into_iter().map(|c| match c {
+    s.chars().map(|c| match c {
         'a' => Signal::A,
         'b' => Signal::B,
         'c' => Signal::C,
@@ -122,18 +288,82 @@ fn parse_digit(s: &str) -> Digit {
         'f' => Signal::F,
         'g' => Signal::G,
          _ => panic!("Unknown character {}", c)
-    }).collect_vec()
+    }).collect()
+}
+
+/// A wiring, along w/ the canonical leds that never light up
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Wiring { rosetta: Rosetta, dead: SegmentSet }
+
+/// wirings: every wiring consistent w/ the observed patterns
+/// digits: possible values of each output digit, over all `wirings`
+/// base: number of glyphs of the font
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Decoding { wirings: Vec<Wiring>, digits: Vec<Vec<u8>>, base: u32 }
+
+impl Decoding {
+    /// The displayed number, only when every output digit has a single possible value, and the number fits a u32
+    fn number(&self) -> Option<u32> {
+        if self.digits.is_empty() { return None; }
+        self.digits.iter().try_fold(0u32, |acc, values| match values.as_slice() {
+            &[d] => acc.checked_mul(self.base)?.checked_add(u32::from(d)),
+            _ => None,
+        })
+    }
+}
+
+/// Decoder for faulty or partial displays: any number of observed patterns (possibly none, possibly repeated), and
+/// leds that may be dead.
+///
+/// Every observed pattern, output digits included, must unscramble to a glyph minus the dead leds. Dead leds are
+/// necessarily among the ones never lit by the observations, so for each of the 7! wirings only the subsets of the
+/// unlit leds are tried.
+fn decode_partial(patterns: &[Digit], outputs: &[Digit], glyphs: &GlyphTable) -> Decoding {
+    // Bit n is set when glyph n matches
+    let matching_glyphs = |leds: SegmentSet, dead: SegmentSet| -> u16 {
+        glyphs.glyphs.iter().enumerate().filter(|&(_, &g)| g - dead == leds).fold(0, |acc, (n, _)| acc | 1 << n)
+    };
+
+    let observed = patterns.iter().chain(outputs).copied().unique().collect_vec();
+    let mut wirings = Vec::<Wiring>::new();
+    let mut candidates = vec![0u16; outputs.len()];
+
+    for permutation in SIGNALS.into_iter().permutations(SIGNALS.len()) {
+        let rosetta: Rosetta = permutation.try_into().unwrap();
+        let leds = observed.iter().map(|d| unscramble(d, &rosetta)).collect_vec();
+        let lit = leds.iter().fold(SegmentSet::EMPTY, |acc, &l| acc | l);
+
+        for dead in (!lit).subsets() {
+            if leds.iter().any(|&l| matching_glyphs(l, dead) == 0) { continue; }
+            wirings.push(Wiring { rosetta, dead });
+            for (c, o) in candidates.iter_mut().zip(outputs) { *c |= matching_glyphs(unscramble(o, &rosetta), dead); }
+        }
+    }
+
+    let digits = candidates.into_iter()
+        .map(|c| (0..glyphs.len() as u8).filter(|n| c & 1 << n != 0).collect())
+        .collect();
+    Decoding { wirings, digits, base: glyphs.len() as u32 }
+}
+
+fn decode_partial_line(line: &str, glyphs: &GlyphTable) -> Decoding {
+    let (s0, s1) = line.split_once('|').unwrap_or(("", line));
+    let patterns = s0.split_whitespace().map(parse_digit).collect_vec();
+    let outputs = s1.split_whitespace().map(parse_digit).collect_vec();
+    decode_partial(&patterns, &outputs, glyphs)
 }
 
 fn translate_digits_from_file(filename: impl AsRef<Path>) -> impl Iterator<Item=Vec<u8>> {
+    let glyphs = GlyphTable::standard();
     common::parse::read_lines(filename)
       .enumerate()
-      .map( |(i, l)| {
+      .map( move |(i, l)| {
         let (s0, s1) = l.split_once('|').expect(format!("Unable to find | on line {}", i).as_str());
-        let ten_digits: [Digit; 10] = s0.split_whitespace().map(parse_digit).collect_vec().try_into().unwrap();
-        let rosetta: Rosetta = signal_rosetta(&ten_digits);
+        let patterns: Vec<Digit> = s0.split_whitespace().map(parse_digit).collect_vec();
         let digits: Vec<Digit> = s1.split_whitespace().map(parse_digit).collect_vec();
-        digits.iter().map(|d| translate_digit(d, &rosetta)).collect_vec()
+        signal_rosetta(&patterns, &glyphs)
+          .and_then(|rosetta| translate_digits(digits.iter(), &rosetta, &glyphs))
+          .unwrap_or_else(|e| panic!("Unable to decode line {}: {:?}", i, e))
       })
 }
 
@@ -142,6 +372,52 @@ fn translate_numbers_from_file(filename: impl AsRef<Path>) -> impl Iterator<Item
     numbers.map(|digits| digits.iter().fold(0u32, |acc, &d| acc*10 + u32::from(d)))
 }
 
+/// Classic 3 rows segment art, one glyph every 4 columns
+/// ```
+///  _     _  _     _  _  _  _  _
+/// | |  | _| _||_||_ |_   ||_||_|
+/// |_|  ||_  _|  | _||_|  ||_| _|
+/// ```
+fn render_segments(sets: &[SegmentSet]) -> [String; 3] {
+    let mut rows = [String::new(), String::new(), String::new()];
+    for &leds in sets {
+        let lit = |s: Signal, c: char| if leds.contains(s) { c } else { ' ' };
+        rows[0].extend([' ', lit(Signal::A, '_'), ' ', ' ']);
+        rows[1].extend([lit(Signal::B, '|'), lit(Signal::D, '_'), lit(Signal::C, '|'), ' ']);
+        rows[2].extend([lit(Signal::E, '|'), lit(Signal::G, '_'), lit(Signal::F, '|'), ' ']);
+    }
+    rows
+}
+
+/// Output digits w/ the raw wires lit on the left, w/ `rosetta` applied in the middle, and the translated values on
+/// the right. Patterns which are not a glyph are shown as `?`.
+fn render_decoded(outputs: &[Digit], rosetta: &Rosetta, glyphs: &GlyphTable) -> String {
+    let raw = render_segments(outputs);
+    let decoded = render_segments(&outputs.iter().map(|d| unscramble(d, rosetta)).collect_vec());
+    let values: String = outputs.iter()
+        .map(|d| translate_digit(d, rosetta, glyphs).map_or('?', |v| char::from_digit(v.into(), 16).unwrap()))
+        .collect();
+
+    let separators = ["    ", " -> ", "    "];
+    let suffixes = [String::new(), format!("= {}", values), String::new()];
+    (0..3).map(|i| format!("{}{}{}{}", raw[i], separators[i], decoded[i], suffixes[i]).trim_end().to_string() + "\n").collect()
+}
+
+/// Renders the output digits of every line, next to the values returned by `translate_digits_from_file`
+fn render_decoded_from_file(filename: impl AsRef<Path>) -> impl Iterator<Item=String> {
+    let glyphs = GlyphTable::standard();
+    common::parse::read_lines(filename)
+      .map( move |l| {
+        let (s0, s1) = l.split_once('|').unwrap_or(("", &l));
+        let patterns: Vec<Digit> = s0.split_whitespace().map(parse_digit).collect_vec();
+        let digits: Vec<Digit> = s1.split_whitespace().map(parse_digit).collect_vec();
+        match signal_rosetta(&patterns, &glyphs) {
+            Ok(rosetta) => render_decoded(&digits, &rosetta, &glyphs),
+            Err(e) => format!("{}Unable to decode: {:?}\n", render_segments(&digits).join("\n") + "\n", e),
+        }
+      })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -162,28 +438,234 @@ mod test {
         &[E, D, B],              // 7: [F, A, C]
     ];
 
-    fn ten_digits() -> [Digit; 10] { TEN_DIGITS.map(|d| d.to_vec()) }
+    fn ten_digits() -> [Digit; 10] { TEN_DIGITS.map(|d| d.iter().copied().collect()) }
 
     #[test]
     fn test_signal_rosetta() {
-        let rosetta = signal_rosetta(&ten_digits());
-        assert_eq!(rosetta, [D, G, B, C, A, E, F]);
+        let rosetta = signal_rosetta(&ten_digits(), &GlyphTable::standard());
+        assert_eq!(rosetta, Ok([D, G, B, C, A, E, F]));
+    }
+
+    #[test]
+    fn test_brute_force_rosetta() {
+        assert_eq!(brute_force_rosetta(&ten_digits(), &GlyphTable::standard()), Ok([D, G, B, C, A, E, F]));
+
+        let glyphs = GlyphTable::standard();
+        for line in common::parse::read_lines("../input/day8_sample.txt") {
+            let (s0, _) = line.split_once('|').unwrap();
+            let ten_digits: [Digit; 10] = s0.split_whitespace().map(parse_digit).collect_vec().try_into().unwrap();
+            assert_eq!(brute_force_rosetta(&ten_digits, &glyphs), signal_rosetta(&ten_digits, &glyphs));
+        }
+    }
+
+    #[test]
+    fn test_validate_rosetta() {
+        let digits = ten_digits();
+        assert_eq!(validate_rosetta(&digits, &[D, G, B, C, A, E, F], &GlyphTable::standard()), Ok(()));
+
+        // Swapping 2 wires breaks some digits
+        assert!(matches!(validate_rosetta(&digits, &[G, D, B, C, A, E, F], &GlyphTable::standard()), Err(WiringError::Inconsistent(_))));
+
+        // Not a permutation
+        assert_eq!(
+            validate_rosetta(&digits, &[D, D, B, C, A, E, F], &GlyphTable::standard()),
+            Err(WiringError::NotAPermutation([D, D, B, C, A, E, F]))
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let ab: Digit = [A, B].into_iter().collect();
+        let bcf: Digit = [B, C, F].into_iter().collect();
+
+        // Pattern 9 replaced by a 2nd 1: heuristic and brute-force both flag the 2 patterns decoding to 1
+        let mut duplicated = ten_digits();
+        duplicated[2] = [B, E].into_iter().collect();
+        let expected = WiringError::Ambiguous(vec![duplicated[0], duplicated[2]]);
+        assert_eq!(signal_rosetta(&duplicated, &GlyphTable::standard()), Err(expected.clone()));
+        assert_eq!(brute_force_rosetta(&duplicated, &GlyphTable::standard()), Err(expected));
+
+        // Several wirings fit: only the patterns decoding differently are named
+        let symmetric = GlyphTable::new(vec![SegmentSet::single(A), SegmentSet::single(B), [A, B, C].into_iter().collect()]);
+        let patterns = [SegmentSet::single(C), SegmentSet::single(D), [C, D, E].into_iter().collect()];
+        assert_eq!(brute_force_rosetta(&patterns, &symmetric), Err(WiringError::Ambiguous(patterns[..2].to_vec())));
+        let sizes = GlyphTable::new(vec![DIGITS[1], DIGITS[7]]);
+        let patterns = [[B, E].into_iter().collect(), [B, D, E].into_iter().collect()];
+        assert_eq!(brute_force_rosetta(&patterns, &sizes), Err(WiringError::Ambiguous(vec![])));
+
+        // Pattern w/ a single led can never be a digit
+        let mut single = ten_digits();
+        single[9] = SegmentSet::single(E);
+        assert_eq!(brute_force_rosetta(&single, &GlyphTable::standard()), Err(WiringError::Inconsistent(vec![single[9]])));
+
+        assert_eq!(translate_digit(&ab, &[A, B, C, D, E, F, G], &GlyphTable::standard()), Err(WiringError::Inconsistent(vec![ab])));
+        assert_eq!(translate_digit(&bcf, &[B, A, C, D, E, F, G], &GlyphTable::standard()), Ok(7));
     }
 
     #[test]
     fn test_translate_digit() {
         let digits: [Digit; 10] = ten_digits();
-        let rosetta = signal_rosetta(&digits);
+        let glyphs = GlyphTable::standard();
+        let rosetta = signal_rosetta(&digits, &glyphs).unwrap();
+
+        let translated = translate_digits(digits.iter(), &rosetta, &glyphs);
+
+        assert_eq!(translated, Ok(vec![1, 8, 9, 6, 4, 5, 0, 3, 2, 7]));
+    }
+
+    #[test]
+    fn test_segment_set() {
+        let abc: SegmentSet = [A, B, C].into_iter().collect();
+        let cd: SegmentSet = [C, D].into_iter().collect();
+        assert_eq!(abc.len(), 3);
+        assert!(abc.contains(B) && !abc.contains(D));
+        assert_eq!(abc & cd, SegmentSet::single(C));
+        assert_eq!(abc | cd, SegmentSet(0b1111));
+        assert_eq!(abc - cd, [A, B].into_iter().collect());
+        assert_eq!(!abc, [D, E, F, G].into_iter().collect());
+        assert_eq!((abc & cd).single_signal(), Some(C));
+        assert_eq!(abc.single_signal(), None);
+        assert!((abc - abc).is_empty());
+        assert_eq!(format!("{:?}", abc), "{abc}");
+    }
+
+    #[test]
+    fn test_subsets() {
+        let subsets = SegmentSet(0b101).subsets().collect_vec();
+        assert_eq!(subsets, vec![SegmentSet(0b101), SegmentSet(0b100), SegmentSet(0b001), SegmentSet(0b000)]);
+        assert_eq!(SegmentSet::ALL.subsets().count(), 128);
+    }
+
+    const LINE_5353: &str = "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
+
+    #[test]
+    fn test_decode_partial_full() {
+        let decoding = decode_partial_line(LINE_5353, &GlyphTable::standard());
+        assert_eq!(decoding.wirings, vec![Wiring { rosetta: [D, E, A, F, G, B, C], dead: SegmentSet::EMPTY }]);
+        assert_eq!(decoding.number(), Some(5353));
+
+        let expected = translate_numbers_from_file("../input/day8_sample.txt");
+        let decoded = common::parse::read_lines("../input/day8_sample.txt").map(|l| decode_partial_line(&l, &GlyphTable::standard()).number());
+        assert!(decoded.eq(expected.map(Some)));
+    }
 
-        let translated = translate_digits(digits.iter(), &rosetta);
+    #[test]
+    fn test_decode_partial_overflow() {
+        let patterns = LINE_5353.split(" | ").next().unwrap();
+        let nines = |n: usize| format!("{} | {}", patterns, vec!["cefabd"; n].join(" "));
+        assert_eq!(decode_partial_line(&nines(9), &GlyphTable::standard()).number(), Some(999_999_999));
+        assert_eq!(decode_partial_line(&nines(10), &GlyphTable::standard()).number(), None);
+    }
+
+    #[test]
+    fn test_decode_partial_missing_patterns() {
+        // Only 1, 4, 7 and 8 are observed. Together w/ the output patterns, they still pin down the wiring.
+        let decoding = decode_partial_line("acedgfb dab eafb ab | cdfeb fcadb cdfeb cdbaf", &GlyphTable::standard());
+        assert_eq!(decoding.wirings, vec![Wiring { rosetta: [D, E, A, F, G, B, C], dead: SegmentSet::EMPTY }]);
+        assert_eq!(decoding.number(), Some(5353));
+
+        // Only the 1 is observed: the output can't be resolved
+        let decoding = decode_partial_line("ab | cdfeb fcadb cdfeb cdbaf", &GlyphTable::standard());
+        assert!(decoding.wirings.contains(&Wiring { rosetta: [D, E, A, F, G, B, C], dead: SegmentSet::EMPTY }));
+        assert!(decoding.digits.iter().all(|values| values.len() > 1));
+        assert_eq!(decoding.number(), None);
+
+        // A lonely 2 leds pattern is a 1, or e.g. a 7 w/ a dead led
+        let decoding = decode_partial_line("ab | ab", &GlyphTable::standard());
+        assert!(decoding.digits[0].contains(&1) && decoding.digits[0].contains(&7));
+        assert_eq!(decoding.number(), None);
+
+        // Nothing to decode
+        assert_eq!(decode_partial_line("ab |", &GlyphTable::standard()).number(), None);
+    }
 
-        assert_eq!(translated, vec![1, 8, 9, 6, 4, 5, 0, 3, 2, 7]);
+    #[test]
+    fn test_decode_partial_dead_led() {
+        // Wire c, i.e. led g, is dead
+        let line = LINE_5353.replace('c', "");
+        let decoding = decode_partial_line(&line, &GlyphTable::standard());
+        assert!(decoding.wirings.iter().all(|w| w.dead == SegmentSet::single(G)));
+        assert_eq!(decoding.number(), Some(5353));
+
+        // Inconsistent observations: a 1 pattern can't be both 'ab' and 'cd'
+        let decoding = decode_partial_line("ab cd acedgfb | ab", &GlyphTable::standard());
+        assert!(decoding.wirings.is_empty());
+        assert_eq!(decoding.digits, vec![Vec::<u8>::new()]);
+        assert_eq!(decoding.number(), None);
+    }
+
+    /// Wires lit for `glyph`
+    fn scramble(glyph: SegmentSet, rosetta: &Rosetta) -> Digit { glyph.iter().map(|s| rosetta[s as usize]).collect() }
+
+    #[test]
+    fn test_glyph_tables() {
+        let rosetta = [D, G, B, C, A, E, F];
+        for glyphs in [GlyphTable::standard(), GlyphTable::hexadecimal(), GlyphTable::alternate()] {
+            let patterns = glyphs.glyphs.iter().rev().map(|&g| scramble(g, &rosetta)).collect_vec();
+            assert_eq!(signal_rosetta(&patterns, &glyphs), Ok(rosetta), "glyphs = {:?}", glyphs);
+            assert_eq!(brute_force_rosetta(&patterns, &glyphs), Ok(rosetta), "glyphs = {:?}", glyphs);
+
+            let values = (0..glyphs.len() as u8).rev().collect_vec();
+            assert_eq!(translate_digits(patterns.iter(), &rosetta, &glyphs), Ok(values));
+        }
+
+        // The alternate 7 has 4 leds, as the 4
+        let alternate = GlyphTable::alternate();
+        assert_eq!(alternate.value_of([A, B, C, F].into_iter().collect()), Some(7));
+        assert_eq!(alternate.value_of([B, C, D, F].into_iter().collect()), Some(4));
+        assert_eq!(alternate.value_of(DIGITS[7]), None);
+
+        // Hexadecimal display of 0xBEEF
+        let hex = GlyphTable::hexadecimal();
+        let patterns = hex.glyphs.iter().map(|&g| scramble(g, &rosetta)).collect_vec();
+        let outputs = [11, 14, 14, 15].map(|n| patterns[n]);
+        let decoding = decode_partial(&patterns, &outputs, &hex);
+        assert_eq!(decoding.number(), Some(0xBEEF));
+    }
+
+    #[test]
+    fn test_missing_glyphs() {
+        let digits = ten_digits();
+        assert_eq!(validate_rosetta(&digits[1..], &[D, G, B, C, A, E, F], &GlyphTable::standard()), Err(WiringError::Missing(vec![1])));
+        assert!(matches!(validate_rosetta(&digits, &[D, G, B, C, A, E, F], &GlyphTable::hexadecimal()), Err(WiringError::Missing(_))));
+    }
+
+    #[test]
+    fn test_render_segments() {
+        let rows = render_segments(&DIGITS);
+        assert_eq!(rows[0].trim_end(), " _       _   _       _   _   _   _   _");
+        assert_eq!(rows[1].trim_end(), "| |   |  _|  _| |_| |_  |_    | |_| |_|");
+        assert_eq!(rows[2].trim_end(), "|_|   | |_   _|   |  _| |_|   | |_|  _|");
+    }
+
+    #[test]
+    fn test_render_decoded() {
+        let (s0, s1) = LINE_5353.split_once('|').unwrap();
+        let patterns = s0.split_whitespace().map(parse_digit).collect_vec();
+        let outputs = s1.split_whitespace().map(parse_digit).collect_vec();
+        let glyphs = GlyphTable::standard();
+        let rosetta = signal_rosetta(&patterns, &glyphs).unwrap();
+
+        let expected = [
+            "     _       _       _   _   _   _",
+            "|_| |_| |_| |_|  -> |_   _| |_   _| = 5353",
+            "| |   | | |   |      _|  _|  _|  _|",
+        ].join("\n") + "\n";
+        assert_eq!(render_decoded(&outputs, &rosetta, &glyphs), expected);
+
+        // A wrong wiring shows up as '?'
+        let wrong = [E, D, A, F, G, B, C];
+        assert!(render_decoded(&outputs, &wrong, &glyphs).contains('?'));
+
+        let rendered = render_decoded_from_file("../input/day8_sample.txt").collect_vec();
+        assert_eq!(rendered.len(), 10);
+        assert!(rendered[0].contains(" = 8394"));
     }
 
     #[test]
     fn test_parse_digit() {
         let digit = parse_digit("abcd");
-        assert_eq!(digit, vec![A, B, C, D]);
+        assert_eq!(digit, SegmentSet(0b1111));
     }
 
     #[test]
@@ -216,4 +698,142 @@ mod test {
         assert_eq!(sum, 61229);
     }
 
+    /// Timing of the bitmask implementation against the former `Vec<Signal>` one, over the full input.
+    /// Run w/ `cargo test --release bench_signal_rosetta -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_signal_rosetta() {
+        use std::time::Instant;
+        const NB_ITERATIONS: usize = 1000;
+
+        let lines = common::parse::read_lines("../input/day8.txt").collect_vec();
+        let parse_line = |l: &String| -> (Vec<Digit>, Vec<Digit>) {
+            let (s0, s1) = l.split_once('|').unwrap();
+            (s0.split_whitespace().map(parse_digit).collect(), s1.split_whitespace().map(parse_digit).collect())
+        };
+        let to_vec = |d: &Digit| d.iter().collect_vec();
+        let inputs = lines.iter().map(parse_line).collect_vec();
+        let legacy_inputs = inputs.iter()
+            .map(|(ten, out)| (ten.iter().map(to_vec).collect_vec(), out.iter().map(to_vec).collect_vec()))
+            .collect_vec();
+
+        let glyphs = GlyphTable::standard();
+        let start = Instant::now();
+        let mut bitmask_sum = 0u64;
+        for _ in 0..NB_ITERATIONS {
+            for (ten, out) in &inputs {
+                let rosetta = signal_rosetta(ten, &glyphs).unwrap();
+                bitmask_sum += out.iter().map(|d| u64::from(translate_digit(d, &rosetta, &glyphs).unwrap())).sum::<u64>();
+            }
+        }
+        let bitmask_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut legacy_sum = 0u64;
+        for _ in 0..NB_ITERATIONS {
+            for (ten, out) in &legacy_inputs {
+                let rosetta = legacy::signal_rosetta(&ten.clone().try_into().unwrap());
+                legacy_sum += out.iter().map(|d| u64::from(legacy::translate_digit(d, &rosetta))).sum::<u64>();
+            }
+        }
+        let legacy_time = start.elapsed();
+
+        println!("SegmentSet:  {:?} for {} passes over {} lines", bitmask_time, NB_ITERATIONS, lines.len());
+        println!("Vec<Signal>: {:?} for {} passes over {} lines", legacy_time, NB_ITERATIONS, lines.len());
+        assert_eq!(bitmask_sum, legacy_sum);
+    }
+
+    /// Former `Vec<Signal>` based implementation, kept as a baseline for `bench_signal_rosetta`
+    mod legacy {
+        use std::collections::HashMap;
+        use itertools::Itertools;
+        use super::super::{common, Signal, Rosetta, SIGNALS};
+
+        type Digit = Vec<Signal>;
+
+        /// The following uses a deterministic way to find the mapping between the wiring -> light-led signals
+        /// ```
+        /// d |       leds    | nb leds
+        /// ---------------------------
+        /// 0 | a b c   e f g | 6
+        /// 1 |     c     f   | 2 *
+        /// 2 | a   c d e   g | 5
+        /// 3 | a   c d   f g | 5
+        /// 4 |   b c d   f   | 4 *
+        /// 5 | a b   d   f g | 5
+        /// 6 | a b   d e f g | 6
+        /// 7 | a   c     f   | 3 *
+        /// 8 | a b c d e f g | 7 *
+        /// 9 | a b c d   f g | 6
+        /// ---------------------------
+        ///     8 6 8 7 4 9 7 
+        ///       *     * *
+        /// b: 6 occurences in all 10 digits
+        /// e: 4 "
+        /// f: 9 "
+        /// c: find number w/ 2 leds. remove     f
+        /// d: "            " 4 leds. remove b c f
+        /// a: "            " 3 leds. remove   c f
+        /// g: "            " 7 leds. remove a b c d e f
+        ///    OR remaining led
+        /// ```
+        /// 
+        /// **TODO**: 
+        /// - Validate that `ten_digits` abide to the structure above. Otherwise it is an invalid input.
+        /// - Maybe convert digits representation to u8 or a bitvec
+        /// - Flagging / discovering leds could be done w/ & | bit-wise operators
+        /// 
+        pub fn signal_rosetta(ten_digits: &[Digit; 10]) -> Rosetta {
+            let led_freqs: HashMap<Signal, u64> = common::collections::freq_count(ten_digits.iter().flatten().copied());
+
+            // Exploratory choice of the moment: Prefer a closure over a macro, to minimize macro usages. 
+            // - Don't know what is the idiomatic way in Rust. Should I prefer a macro or clojure in general for readability
+            //   and debugging? Are macro are harder to debug than closures and vice-versa. 
+            // - Exploring the clojure way at the moment.
+            // Note: I miss being able to create closure w/ the function definition syntax as in Scala, 
+            //       In Rust function  are pure and don't capture a lexical scope (they have to be unstateful).
+            let led_with_freq = |n: u64| { led_freqs.iter().find(|(_, &f)| f == n).expect(format!("Unable to find a led w/ {} occurences", n).as_str()) };
+            let digit_with_leds = |n: usize| { ten_digits.iter().find(|leds| leds.len() == n).expect(format!("Unable to find digit w/ {} leds", n).as_str()) };
+
+            fn remaining_led(digit: &Digit, to_remove: &[Signal]) -> Signal {
+                // Assumes there's only one
+                *digit.iter().find(|led| ! to_remove.iter().contains(led))
+                    .expect(format!("Unable remaining led for digit= {:#?}, while removing {:?}", digit, to_remove).as_str())
+            }
+
+            let (&f, _) = led_with_freq(9);
+            let (&e, _) = led_with_freq(4);
+            let (&b, _) = led_with_freq(6);
+
+            let one   = digit_with_leds(2);
+            let c = remaining_led(one, &[f]);
+
+            let four  = digit_with_leds(4);
+            let d = remaining_led(four, &[b, c, f]);
+
+            let seven = digit_with_leds(3);
+            let a = remaining_led(seven, &[c, f]);
+
+            let g = remaining_led(&SIGNALS.to_vec(), &[a, b, c, d, e, f]);
+
+            [a, b, c, d, e, f, g]
+        }
+
+        pub fn translate_digit(digit: &Digit, rosetta: &Rosetta) -> u8 {
+            // Start by matching on length of lens
+            match digit.len() {
+                2 => 1,
+                4 => 4,
+                3 => 7,
+                7 => 8,
+                5 if digit.contains(&rosetta[Signal::E as usize]) => 2,
+                5 if digit.contains(&rosetta[Signal::B as usize]) => 5,
+                5 => 3,
+                6 if !digit.contains(&rosetta[Signal::D as usize]) => 0,
+                6 if !digit.contains(&rosetta[Signal::C as usize]) => 6,
+                6 if !digit.contains(&rosetta[Signal::E as usize]) => 9,
+                _ => panic!("Impossible digit {:#?}", digit)
+            }
+        }
+    }
 }
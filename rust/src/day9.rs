@@ -1,39 +1,113 @@
-
-use std::collections::VecDeque;
-use std::convert::Infallible;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::result::Result;
 use std::str::FromStr;
 use itertools::Itertools;
 use ndarray::Array2;
 use tailcall::tailcall;
+use super::common::collections::DisjointSets;
+use super::common::parse::{self, DigitGridParseError};
+use super::common::render::{self, Rgb};
 use super::common::macros::when;
 
 #[derive(Debug, PartialEq)]
-struct HeightMap { heights: Array2<u8> }
+struct HeightMap { heights: Array2<u8>, options: HeightMapOptions }
 
 type Index = (usize, usize);
 
+/// Cells considered adjacent
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Neighbourhood {
+  /// up, down, left, right
+  VonNeumann,
+  /// the 8 surrounding cells
+  Moore,
+  /// "odd-r" hexagonal layout: odd rows are shifted half a cell to the right
+  Hex,
+}
+
+impl Neighbourhood {
+  /// (row, column) offsets of the adjacent cells, for a cell on row `i`
+  fn offsets(self, i: usize) -> &'static [(isize, isize)] {
+    match self {
+      Neighbourhood::VonNeumann => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+      Neighbourhood::Moore => &[(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
+      Neighbourhood::Hex if i & 1 == 0 => &[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)],
+      Neighbourhood::Hex => &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
+    }
+  }
+}
+
+/// Whether a minima must be lower than all of its adjacents, or only lower or equal
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Minima { Strict, NonStrict }
+
+/// Heights delimiting the basins
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Wall {
+  /// Ridges: heights >= the threshold
+  AtLeast(u8),
+  /// Only the given height
+  Exactly(u8),
+  /// No wall, the whole map is a single basin per connected region
+  Nothing,
+}
+
+impl Wall {
+  fn contains(&self, h: u8) -> bool {
+    match *self {
+      Wall::AtLeast(threshold) => h >= threshold,
+      Wall::Exactly(height) => h == height,
+      Wall::Nothing => false,
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct HeightMapOptions { neighbourhood: Neighbourhood, wall: Wall, minima: Minima }
+
+impl Default for HeightMapOptions {
+  fn default() -> Self {
+    HeightMapOptions { neighbourhood: Neighbourhood::VonNeumann, wall: Wall::AtLeast(9), minima: Minima::Strict }
+  }
+}
+
+/// labels: basin of each cell, `None` for the walls
+/// sizes: number of cells of each basin, indexed by label
+#[derive(Debug, PartialEq)]
+struct Basins { labels: Array2<Option<usize>>, sizes: Vec<usize> }
+
 
 impl HeightMap {
+  fn new(heights: Array2<u8>) -> HeightMap { HeightMap { heights, options: HeightMapOptions::default() } }
+
   #[allow(dead_code)]
   fn zeros(rows: usize, columns: usize) -> HeightMap { 
-    HeightMap { heights: Array2::zeros((rows, columns)) }
+    HeightMap::new(Array2::zeros((rows, columns)))
+  }
+
+  fn with_neighbourhood(self, neighbourhood: Neighbourhood) -> Self {
+    HeightMap { options: HeightMapOptions { neighbourhood, ..self.options }, ..self }
+  }
+  fn with_wall(self, wall: Wall) -> Self {
+    HeightMap { options: HeightMapOptions { wall, ..self.options }, ..self }
   }
-  fn from_shape_vec(dim: Index, numbers: Vec<u8>) -> HeightMap {
-    HeightMap { heights: Array2::from_shape_vec(dim, numbers).expect("Invalid HeightMap Shape ") }
+  fn with_minima(self, minima: Minima) -> Self {
+    HeightMap { options: HeightMapOptions { minima, ..self.options }, ..self }
   }
 
+  fn is_wall(&self, v: u8) -> bool { self.options.wall.contains(v) }
+
   fn get_adjacents(&self, (i, j): Index) -> Vec<(Index, u8)> {
-    let up    = when!(i > 0,          (i - 1, j));
-    let down  = when!(i < usize::MAX, (i + 1, j));
-    let left  = when!(j > 0,          (i, j - 1));
-    let right = when!(j < usize::MAX, (i, j + 1));
-    [up, down, left, right]
-      .into_iter()
-      .flatten()
-      .filter_map(|ij| self.get(ij).map(|v| (ij, v))
-      ).collect()
+    self.options.neighbourhood
+      .offsets(i)
+      .iter()
+      .filter_map(|&(di, dj)| Some((i.checked_add_signed(di)?, j.checked_add_signed(dj)?)))
+      .filter_map(|ij| self.get(ij).map(|v| (ij, v)))
+      .collect()
   } 
 
   fn get(&self, ij: Index) -> Option<u8> { self.heights.get(ij).copied() }
@@ -42,10 +116,11 @@ impl HeightMap {
     let center = self.get(ij);
     let min_adjacents = self.get_adjacents(ij).into_iter().map(|(_, v)| v).min();
     
-    match (center, min_adjacents) {
-      (Some(c), Some(m)) => c < m,
-      (None, _) => false,
-      (_, None) => true,
+    match (center, min_adjacents, self.options.minima) {
+      (Some(c), Some(m), Minima::Strict) => c < m,
+      (Some(c), Some(m), Minima::NonStrict) => c <= m,
+      (None, _, _) => false,
+      (_, None, _) => true,
     }
   }
 
@@ -58,17 +133,36 @@ impl HeightMap {
   }
 
   fn largest_basin_sizes(&self, nth_largest: usize) -> Vec<usize> {
-    self
-      .minimas()
-      .into_iter()
-      // .map(|(ij, _)| self.basin_tailrec(ij).1 )
-      .map(|(ij, _)| self.basin_loop(ij) )
-      .fold(Vec::<usize>::new(), |mut largest, sz| {
-        largest.push(sz);
-        largest.sort_by(|a, b| b.cmp(a));
-        if largest.len() > nth_largest { largest.pop(); }
-        largest
-      })
+    let Basins { sizes, .. } = self.basins();
+    sizes.into_iter().sorted_by(|a, b| b.cmp(a)).take(nth_largest).collect()
+  }
+
+  /// Labels every non-wall cell w/ its basin, in a single union-find pass over the cells.
+  /// Basins are the connected regions delimited by walls, so plateaus and regions w/o a strict minimum are handled too.
+  /// Labels are numbered in row-major order of the basins' first cell.
+  fn basins(&self) -> Basins {
+    let (_, nb_columns) = self.heights.dim();
+    let flat = |(i, j): Index| i * nb_columns + j;
+    let is_basin = |v: u8| !self.is_wall(v);
+
+    let mut sets = DisjointSets::new(self.heights.len());
+    for (ij, _) in self.heights.indexed_iter().filter(|(_, &v)| is_basin(v)) {
+      for (adj, _) in self.get_adjacents(ij).into_iter().filter(|&(_, v)| is_basin(v)) {
+        sets.union(flat(ij), flat(adj));
+      }
+    }
+
+    let mut root_labels: HashMap<usize, usize> = HashMap::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let labels = Array2::from_shape_fn(self.heights.dim(), |ij| {
+      if !is_basin(self.heights[ij]) { return None; }
+      let root = sets.find(flat(ij));
+      let label = *root_labels.entry(root).or_insert_with(|| { sizes.push(0); sizes.len() - 1 });
+      sizes[label] += 1;
+      Some(label)
+    });
+
+    Basins { labels, sizes }
   }
 
   /// Tailcall exploration. For this one, since both `visited and `to_visit` are mutable structures, I tend to think
@@ -87,7 +181,7 @@ impl HeightMap {
         let mut adjacents_to_visit: VecDeque<Index> = hmap
           .get_adjacents(ij)
           .into_iter()
-          .filter_map(|(ij, v)| when!(center < v && v < 9, ij) ) // SMALL-OPTME: Call already_visited to make less tailcalls.
+          .filter_map(|(ij, v)| when!(center < v && !hmap.is_wall(v), ij) ) // SMALL-OPTME: Call already_visited to make less tailcalls.
           .collect();
         
         to_visit.append(&mut adjacents_to_visit);
@@ -106,52 +200,69 @@ impl HeightMap {
     HeightMap::basin_impl(self, visited, to_visit, 0)
   }
 
-  fn basin_loop(&self, root_ij: Index) -> usize {
-    let mut visited = Array2::<bool>::default(self.heights.dim());
-    let mut to_visit: VecDeque<Index> = VecDeque::new();
-    let mut nb_visited: usize = 0;
+  #[allow(dead_code)]
+  fn basin_size(basin: &Array2<bool>) -> usize { basin.iter().copied().map_into::<usize>().sum() }
+}
 
-    to_visit.push_back(root_ij);
+/// Rendering of the labelled basins: ridges (9s) are walls, and minimas are highlighted
+impl HeightMap {
+  const WALL: Rgb = Rgb(48, 48, 48);
+  const MINIMA: Rgb = Rgb(255, 255, 255);
 
-    while let Some(ij) = to_visit.pop_front() {
-      let already_visited = visited.get(ij).copied().unwrap_or(true); // out-of-bound is considered already visited
-      if already_visited { continue; }
+  /// Letters wrap around after 26 basins, so on a real input many basins share one. Adjacent cells of distinct
+  /// basins are still separated by walls, and `render_ansi` colours each basin apart
+  fn basin_letter(label: usize) -> char { (b'a' + (label % 26) as u8) as char }
 
-      let center = self.get(ij).expect("unexpected. all indexes at this point should be valid and unvisited");
-      let mut adjacents_to_visit: VecDeque<Index> = self
-        .get_adjacents(ij)
-        .into_iter()
-        .filter_map(|(ij, v)| when!(center < v && v < 9, ij) ) // small-optme: call already_visited to make less tailcalls.
-          .collect();
-        
-        to_visit.append(&mut adjacents_to_visit);
-        visited[ij] = true;
-        nb_visited += 1;
+  /// One letter per basin, upper-cased on the minimas. Walls are `#`
+  fn render_ascii(&self) -> String {
+    self.render_cells(|_, c| c.to_string())
+  }
+
+  /// Same as `render_ascii`, w/ each basin on a distinct background colour
+  fn render_ansi(&self) -> String {
+    self.render_cells(|label, c| {
+      let bg = label.map_or(Self::WALL, render::palette);
+      format!("{}{}{}", render::ansi_bg(bg), c, render::ANSI_RESET)
+    })
+  }
+
+  fn render_cells(&self, cell: impl Fn(Option<usize>, char) -> String) -> String {
+    let Basins { labels, .. } = self.basins();
+    let mut is_minima = Array2::from_elem(labels.dim(), false);
+    for (ij, _) in self.minimas() { is_minima[ij] = true; }
+    let mut rendered = String::new();
+    for (i, row) in labels.rows().into_iter().enumerate() {
+      for (j, label) in row.iter().enumerate() {
+        let c = match label {
+          None => '#',
+          Some(l) if is_minima[(i, j)] => Self::basin_letter(*l).to_ascii_uppercase(),
+          Some(l) => Self::basin_letter(*l),
+        };
+        rendered += &cell(*label, c);
+      }
+      rendered.push('\n');
     }
-    
-    nb_visited
+    rendered
   }
 
-  #[allow(dead_code)]
-  fn basin_size(basin: &Array2<bool>) -> usize { basin.iter().copied().map_into::<usize>().sum() }
+  /// PPM image, each cell drawn as a `scale` x `scale` square
+  fn render_ppm(&self, scale: usize) -> Vec<u8> {
+    let Basins { labels, .. } = self.basins();
+    let mut pixels = labels.map(|l| l.map_or(Self::WALL, render::palette));
+    for (ij, _) in self.minimas() { pixels[ij] = Self::MINIMA; }
+    render::ppm(&pixels, scale)
+  }
+
+  fn save_ppm(&self, path: impl AsRef<Path>, scale: usize) -> io::Result<()> { fs::write(path, self.render_ppm(scale)) }
 }
 
+/// Positions are 1-based, and refer to the lines of the parsed string, blank ones included
+pub type HeightMapParseError = DigitGridParseError;
+
+/// Trailing whitespaces and blank lines are ignored
 impl FromStr for HeightMap {
-    type Err = Infallible; // TODO: Change to !
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-      let nb_columns = s.chars().take_while(|&c| c != '\n').count();
-      let nb_rows = s.split('\n').count();
-
-      let numbers = s .chars()
-        .filter_map(|c| match c {
-          '0'..='9' => c.to_digit(10).map(|d| d as u8),
-          '\n' | '\t' => None,
-          c if c.is_whitespace() => None,
-          _ => panic!("Invalid char {}. Must be [0-9] or '\n'", c)
-        }).collect_vec();
-
-      Ok( HeightMap::from_shape_vec((nb_rows, nb_columns), numbers) )
-    }
+    type Err = HeightMapParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> { parse::digit_grid(s).map(HeightMap::new) }
 }
 
 
@@ -164,8 +275,9 @@ mod test {
   use ndarray::Array2;
   use std::fs;
 
-  use super::HeightMap;
-use super::Index;
+  use super::{Basins, HeightMap, HeightMapParseError, Minima, Neighbourhood, Wall};
+  use crate::common::render::{self, Rgb};
+  use super::Index;
 
   const HEIGHT_MAP_STR: &str = "2199943210
 3987894921
@@ -175,13 +287,13 @@ use super::Index;
 
   /// **ANSME**: Couldn't figure why I couldn't define a `let height_map =` here instead. Maybe a let cannot be defined
   /// at the `mod` level
-  macro_rules! HEIGHT_MAP { () => { HeightMap { heights: 
+  macro_rules! HEIGHT_MAP { () => { HeightMap::new(
     array![[ 2,1,9,9,9,4,3,2,1,0 ],
            [ 3,9,8,7,8,9,4,9,2,1 ],
            [ 9,8,5,6,7,8,9,8,9,2 ],
            [ 8,7,6,7,8,9,6,7,8,9 ],
            [ 9,8,9,9,9,6,5,6,7,8 ]]
-  } } }
+  ) } }
 
   #[test] 
   fn test_from_str() {
@@ -189,6 +301,23 @@ use super::Index;
     assert_eq!(height_map, HEIGHT_MAP!());
   }
 
+  #[test]
+  fn test_from_str_tolerance() {
+    let padded = format!("\n{}\n  \n", HEIGHT_MAP_STR.replace('\n', " \t\n"));
+    assert_eq!(padded.parse::<HeightMap>(), Ok(HEIGHT_MAP!()));
+    assert_eq!(format!("{}\r\n", HEIGHT_MAP_STR.replace('\n', "\r\n")).parse::<HeightMap>(), Ok(HEIGHT_MAP!()));
+  }
+
+  #[test]
+  fn test_from_str_errors() {
+    type E = HeightMapParseError;
+    assert_eq!("123\n4x6".parse::<HeightMap>(), Err(E::InvalidChar { row: 2, column: 2, c: 'x' }));
+    assert_eq!("123\n4 56".parse::<HeightMap>(), Err(E::InvalidChar { row: 2, column: 2, c: ' ' }));
+    assert_eq!("123\n\n456\n78".parse::<HeightMap>(), Err(E::RaggedRow { row: 4, nb_columns: 2, expected: 3 }));
+    assert_eq!("123\n4567".parse::<HeightMap>(), Err(E::RaggedRow { row: 2, nb_columns: 4, expected: 3 }));
+    assert_eq!(" \n\n".parse::<HeightMap>(), Err(E::Empty));
+  }
+
   #[test]
   fn test_is_minima() {
     let height_map = HEIGHT_MAP!();
@@ -210,7 +339,7 @@ use super::Index;
   #[test]
   fn test_part1() {
     let content = fs::read_to_string("../input/day9.txt").unwrap();
-    let height_map: HeightMap = content.parse::<HeightMap>().unwrap(); // TODO: replace by into_ok() when ! gets stabilized
+    let height_map: HeightMap = content.parse::<HeightMap>().unwrap();
     let sum: u32 = height_map.minimas().into_iter().map(|(_, v)| v as u32).sum();
     println!("day9 part 1 answer = {}", sum);
     assert_eq!(sum, 494);
@@ -240,6 +369,131 @@ use super::Index;
     ]);  
   }
 
+  #[test]
+  fn test_basins_labels() {
+    let height_map = HEIGHT_MAP!();
+    let basins = height_map.basins();
+    assert_eq!(basins.sizes, vec![3, 9, 14, 9]);
+    assert_eq!(basins.labels.iter().filter(|l| l.is_none()).count(), 50 - 35);
+
+    // Same basins as the ones found from the minimas
+    for (label, center) in [(0, (0, 1)), (1, (0, 9)), (2, (2, 2)), (3, (4, 6))] {
+      let (expected, _) = height_map.basin_tailrec(center);
+      assert_eq!(basins.labels.map(|&l| l == Some(label)), expected);
+    }
+  }
+
+  #[test]
+  fn test_basins_plateau() {
+    // No strict minima, but still 2 basins
+    let height_map = "5559555\n5559555\n9999999\n5555555".parse::<HeightMap>().unwrap();
+    assert!(height_map.minimas().is_empty());
+
+    let Basins { labels, sizes } = height_map.basins();
+    assert_eq!(sizes, vec![6, 6, 7]);
+    assert_eq!(labels[(0, 0)], Some(0));
+    assert_eq!(labels[(1, 6)], Some(1));
+    assert_eq!(labels[(2, 0)], None);
+    assert_eq!(labels[(3, 3)], Some(2));
+  }
+
+  #[test]
+  fn test_render_ascii() {
+    let expected = "\
+aA###bbbbB
+a#ccc#b#bb
+#cCccc#d#b
+ccccc#ddd#
+#c###dDddd
+";
+    assert_eq!(HEIGHT_MAP!().render_ascii(), expected);
+    assert_eq!((HeightMap::basin_letter(25), HeightMap::basin_letter(26)), ('z', 'a'));
+  }
+
+  #[test]
+  fn test_render_ansi() {
+    let rendered = HEIGHT_MAP!().render_ansi();
+    println!("{}", rendered);
+    assert_eq!(rendered.lines().count(), 5);
+    assert_eq!(rendered.matches("\x1b[0m").count(), 50);
+    // Same basin, same colour
+    let first_line = rendered.lines().next().unwrap();
+    assert!(first_line.starts_with(&format!("{}a", render::ansi_bg(render::palette(0)))));
+    assert!(first_line.contains(&format!("{}A", render::ansi_bg(render::palette(0)))));
+  }
+
+  #[test]
+  fn test_render_ppm() {
+    let ppm = HEIGHT_MAP!().render_ppm(2);
+    let header = "P6\n20 10\n255\n";
+    assert!(ppm.starts_with(header.as_bytes()));
+    assert_eq!(ppm.len(), header.len() + 20 * 10 * 3);
+
+    let pixel = |x: usize, y: usize| { let k = header.len() + (y * 20 + x) * 3; Rgb(ppm[k], ppm[k+1], ppm[k+2]) };
+    assert_eq!(pixel(0, 0), render::palette(0));
+    assert_eq!(pixel(3, 1), HeightMap::MINIMA);
+    assert_eq!(pixel(5, 0), HeightMap::WALL);
+  }
+
+  #[test]
+  fn test_save_ppm() {
+    let content = fs::read_to_string("../input/day9.txt").unwrap();
+    let height_map: HeightMap = content.parse::<HeightMap>().unwrap();
+    let path = std::env::temp_dir().join("day9_basins.ppm");
+    height_map.save_ppm(&path, 4).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), height_map.render_ppm(4));
+    println!("day9 basins written to {}", path.display());
+  }
+
+  #[test]
+  fn test_neighbourhoods() {
+    let height_map = HeightMap::zeros(3, 3);
+    let adjacents = |hmap: &HeightMap, ij| hmap.get_adjacents(ij).into_iter().map(|(ij, _)| ij).sorted().collect_vec();
+
+    assert_eq!(adjacents(&height_map, (0, 0)), vec![(0, 1), (1, 0)]);
+    assert_eq!(adjacents(&height_map, (1, 1)), vec![(0, 1), (1, 0), (1, 2), (2, 1)]);
+
+    let moore = height_map.with_neighbourhood(Neighbourhood::Moore);
+    assert_eq!(adjacents(&moore, (0, 0)), vec![(0, 1), (1, 0), (1, 1)]);
+    assert_eq!(adjacents(&moore, (1, 1)).len(), 8);
+
+    let hex = moore.with_neighbourhood(Neighbourhood::Hex);
+    assert_eq!(adjacents(&hex, (1, 1)), vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+    assert_eq!(adjacents(&hex, (2, 1)), vec![(1, 0), (1, 1), (2, 0), (2, 2)]);
+  }
+
+  #[test]
+  fn test_basins_options() {
+    // 2 basins touching diagonally only
+    let height_map = "0199\n9919\n9991".parse::<HeightMap>().unwrap();
+    assert_eq!(height_map.basins().sizes, vec![2, 1, 1]);
+
+    let moore = height_map.with_neighbourhood(Neighbourhood::Moore);
+    assert_eq!(moore.basins().sizes, vec![4]);
+
+    // Lowering the ridges splits the basin
+    let lower_walls = HEIGHT_MAP!().with_wall(Wall::AtLeast(8));
+    assert_eq!(lower_walls.largest_basin_sizes(3), vec![9, 7, 6]);
+    assert_eq!(HEIGHT_MAP!().with_wall(Wall::Nothing).largest_basin_sizes(3), vec![50]);
+
+    // A moat of 5s, the 9s now belonging to the basins
+    let moat = "191\n555\n191".parse::<HeightMap>().unwrap();
+    assert_eq!(moat.largest_basin_sizes(3), vec![7]);
+    assert_eq!(moat.with_wall(Wall::Exactly(5)).largest_basin_sizes(3), vec![3, 3]);
+  }
+
+  #[test]
+  fn test_non_strict_minima() {
+    let height_map = "5559555\n5559555\n9999999\n5555555".parse::<HeightMap>().unwrap();
+    assert!(height_map.minimas().is_empty());
+
+    let non_strict = height_map.with_minima(Minima::NonStrict);
+    assert!(non_strict.is_minima((0, 0)));
+    assert!(!non_strict.is_minima((0, 3)));
+    assert_eq!(non_strict.minimas().len(), 19);
+    assert_eq!(HEIGHT_MAP!().with_minima(Minima::NonStrict).minimas().len(), 4);
+  }
+
   #[test]
   fn test_largest_basin_sizes() {
     let height_map = HEIGHT_MAP!();
@@ -250,7 +504,7 @@ use super::Index;
   #[test]
   fn test_part2() {
     let content = fs::read_to_string("../input/day9.txt").unwrap();
-    let height_map: HeightMap = content.parse::<HeightMap>().unwrap(); // TODO: replace by into_ok() when ! gets stabilized
+    let height_map: HeightMap = content.parse::<HeightMap>().unwrap();
     let largest = height_map.largest_basin_sizes(3);
     let answer = largest.into_iter().product::<usize>();
     println!("day9 part 2 answer = {}", answer);
@@ -15,7 +15,62 @@ mod day13;
 mod day14;
 mod day15;
 
+use std::path::Path;
+use std::time::Duration;
+
+const USAGE: &str = "usage: aoc2021 [animate --day 11 [--steps N] [--delay MS] [--frames DIR]]";
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => run_all(),
+        Some("animate") => animate(&args[1..]),
+        Some(_) => usage_error("unknown command"),
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2)
+}
+
+fn option_value<'a, T: std::str::FromStr>(name: &str, args: &mut impl Iterator<Item=&'a String>) -> T {
+    args.next()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| usage_error(&format!("missing or invalid value for {}", name)))
+}
+
+/// Plays a day's simulation in the terminal
+fn animate(args: &[String]) {
+    let mut day: Option<u8> = None;
+    let mut nb_steps: Option<usize> = None;
+    let mut delay_ms: u64 = 100;
+    let mut frames: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--day" => day = Some(option_value("--day", &mut args)),
+            "--steps" => nb_steps = Some(option_value("--steps", &mut args)),
+            "--delay" => delay_ms = option_value("--delay", &mut args),
+            "--frames" => frames = Some(option_value("--frames", &mut args)),
+            _ => usage_error(&format!("unknown option {}", arg)),
+        }
+    }
+
+    let delay = Duration::from_millis(delay_ms);
+    let result = match day {
+        Some(11) => day11::animate(nb_steps, delay, frames.as_deref().map(Path::new)),
+        Some(d) => usage_error(&format!("no animation for day {}", d)),
+        None => usage_error("--day is required"),
+    };
+    if let Err(e) = result {
+        eprintln!("animation failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run_all() {
     println!("day1-1 answer = {}", day1::part1());
     println!("day1-2 answer = {}", day1::part2());
     println!("day2-1 answer = {}", day2::part1());
@@ -27,4 +82,5 @@ fn main() {
     println!("day5-1 answer = {}", day5::part1());
     println!("day5-2 answer = {}", day5::part2());
     day6::part1_and_2();
+    day11::part1_and_2();
 }